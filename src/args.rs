@@ -0,0 +1,157 @@
+use std::io;
+use colored::*;
+
+// A builtin's arguments: single-letter flags, flags that took a value, and operands
+#[derive(Default)]
+pub struct ParsedArgs<'a> {
+    pub flags: String,
+    values: Vec<(char, &'a str)>,
+    pub operands: Vec<&'a str>,
+}
+
+impl ParsedArgs<'_> {
+    pub fn has(&self, flag: char) -> bool {
+        self.flags.contains(flag)
+    }
+
+    // The last value given for `flag`
+    pub fn value(&self, flag: char) -> Option<&str> {
+        self.values.iter().rev().find(|(c, _)| *c == flag).map(|(_, value)| *value)
+    }
+}
+
+// Splits `-rf`-style flags from operands, rejecting flags the command doesn't know. Flags
+// in `with_value` take an argument (`-n 5` or `-n5`).
+pub fn parse_args<'a>(cmd: &str, args: &[&'a str], allowed: &str, with_value: &str) -> io::Result<ParsedArgs<'a>> {
+    let mut parsed = ParsedArgs::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let cluster = match arg.strip_prefix('-') {
+            Some(cluster) if !cluster.is_empty() => cluster,
+            _ => {
+                parsed.operands.push(*arg);
+                continue;
+            }
+        };
+        // `head -20` is shorthand for `head -n 20`
+        if with_value.contains('n') && cluster.chars().all(|c| c.is_ascii_digit()) {
+            parsed.values.push(('n', cluster));
+            continue;
+        }
+        for (i, c) in cluster.char_indices() {
+            if with_value.contains(c) {
+                let rest = &cluster[i + c.len_utf8()..];
+                let value = match rest {
+                    "" => iter.next().copied().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput,
+                        format!("{}: option -{} requires an argument", cmd, c)))?,
+                    rest => rest,
+                };
+                parsed.values.push((c, value));
+                break;
+            }
+            if !allowed.contains(c) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{}: unknown option -{}", cmd, c)));
+            }
+            parsed.flags.push(c);
+        }
+    }
+    Ok(parsed)
+}
+
+pub fn usage(text: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, format!("Usage: {}", text))
+}
+
+// Per-operand failures are reported and the remaining operands still processed
+pub fn report(cmd: &str, operand: &str, e: &io::Error) {
+    println!("{}: {}: {}: {}", "Error".red(), cmd, operand, e);
+    crate::history::mark_failed();
+}
+
+// Splits a command line into words. Single quotes keep everything literally, double quotes
+// keep whitespace, and a backslash outside single quotes escapes the next character.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), c) => word.push(c),
+            (_, '\\') => {
+                in_word = true;
+                word.extend(chars.next());
+            }
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                in_word = true;
+                quote = Some(c);
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+// Quotes `word` so that `split_words` reads it back as a single word
+pub fn quote(word: &str) -> String {
+    let plain = !word.is_empty() && !word.chars().any(|c| c.is_whitespace() || matches!(c, '\'' | '"' | '\\'));
+    if plain {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_args_splits_flags_values_and_operands() {
+        let parsed = parse_args("head", &["-qn", "5", "a", "-", "-n3", "b"], "q", "n").unwrap();
+        assert!(parsed.has('q'));
+        assert_eq!(parsed.value('n'), Some("3"));
+        assert_eq!(parsed.operands, ["a", "-", "b"]);
+    }
+
+    #[test]
+    fn parse_args_rejects_unknown_and_incomplete_flags() {
+        assert!(parse_args("rm", &["-x"], "rf", "").is_err());
+        assert!(parse_args("head", &["-n"], "", "n").is_err());
+    }
+
+    #[test]
+    fn parse_args_reads_a_bare_count() {
+        assert_eq!(parse_args("head", &["-20"], "", "n").unwrap().value('n'), Some("20"));
+        assert!(parse_args("rm", &["-20"], "rf", "").is_err());
+    }
+
+    #[test]
+    fn split_words_honours_quotes_and_escapes() {
+        assert_eq!(split_words("  cat 'my file'  \"a \\\" b\" c\\ d ''"), ["cat", "my file", "a \" b", "c d", ""]);
+        assert_eq!(split_words("echo 'it'\\''s'"), ["echo", "it's"]);
+    }
+
+    #[test]
+    fn quote_round_trips_through_split_words() {
+        assert_eq!(quote("plain/path.rs"), "plain/path.rs");
+        for word in ["my file", "it's", "back\\slash", "\"q\"", ""] {
+            assert_eq!(split_words(&quote(word)), [word]);
+        }
+    }
+}
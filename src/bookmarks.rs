@@ -0,0 +1,86 @@
+use std::io;
+use std::path::PathBuf;
+use colored::*;
+use crate::config::Config;
+use crate::Environment;
+
+// Expands a leading `@name` in an argument to the bookmarked directory
+pub fn expand(arg: &str, config: &Config) -> String {
+    let Some(rest) = arg.strip_prefix('@') else { return arg.to_string() };
+    let (name, tail) = match rest.split_once('/') {
+        Some((name, tail)) => (name, Some(tail)),
+        None => (rest, None),
+    };
+
+    match config.bookmarks.get(name) {
+        Some(dir) => match tail {
+            Some(tail) => dir.join(tail).display().to_string(),
+            None => dir.display().to_string(),
+        },
+        // Not a bookmark, so leave things like `@latest` alone
+        None => arg.to_string(),
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+pub fn execute_mark(args: &[&str], env: &Environment, config: &mut Config) -> io::Result<()> {
+    let Some(name) = args.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: mark <name> [dir]"));
+    };
+    if !valid_name(name) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            "Bookmark names may only contain letters, digits, '-', '_' and '.'"));
+    }
+
+    let dir = match args.get(1) {
+        Some(path) => env.resolve_path(path),
+        None => env.current_dir.clone(),
+    };
+    if !dir.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Directory not found"));
+    }
+    let dir = dir.canonicalize().unwrap_or(dir);
+
+    config.bookmarks.insert(name.to_string(), dir.clone());
+    config.save()?;
+    println!("{} {} → {}", "Bookmarked".green(), name.bright_cyan(), env.tilde_path(&dir));
+    Ok(())
+}
+
+pub fn execute_unmark(args: &[&str], config: &mut Config) -> io::Result<()> {
+    let Some(name) = args.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: unmark <name>"));
+    };
+    if config.bookmarks.remove(*name).is_none() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("No bookmark named '{}'", name)));
+    }
+    config.save()?;
+    println!("{} {}", "Removed bookmark".green(), name.bright_cyan());
+    Ok(())
+}
+
+pub fn execute_jump(args: &[&str], env: &mut Environment, config: &Config) -> io::Result<()> {
+    let Some(name) = args.first() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: jump <name>"));
+    };
+    let dir: PathBuf = config.bookmarks.get(*name)
+        .cloned()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No bookmark named '{}'", name)))?;
+    env.set_directory(dir)
+}
+
+pub fn execute_marks(env: &Environment, config: &Config) {
+    if config.bookmarks.is_empty() {
+        println!("{}", "No bookmarks yet. Use 'mark <name>' to add one.".yellow());
+        return;
+    }
+    let width = config.bookmarks.keys().map(|name| name.chars().count()).max().unwrap_or(0);
+    for (name, dir) in &config.bookmarks {
+        let path = env.tilde_path(dir);
+        let path = if dir.is_dir() { path.normal() } else { path.red().strikethrough() };
+        println!("  {}  {}", format!("{:<width$}", name, width = width).bright_cyan(), path);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use colored::*;
+use serde::{Deserialize, Serialize};
+use crate::{Session, Voia};
+
+const FORMAT: &str = "partermai-session";
+const VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct VoiaBundle {
+    model: String,
+    conversation: Vec<String>,
+}
+
+// A session as JSON that can move between machines: paths under the home directory are
+// kept as ~/..., and the Voia API key stays behind
+#[derive(Serialize, Deserialize)]
+pub struct SessionBundle {
+    format: String,
+    version: u32,
+    pub name: String,
+    exported_at: String,
+    cwd: String,
+    #[serde(default)]
+    previous_dir: Option<String>,
+    #[serde(default)]
+    dir_stack: Vec<String>,
+    #[serde(default)]
+    vars: BTreeMap<String, String>,
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    history: Vec<String>,
+    #[serde(default)]
+    voia_persona: Option<String>,
+    #[serde(default)]
+    voia: Option<VoiaBundle>,
+}
+
+pub fn export(session: &Session, target: &Path, out: &mut impl Write) -> io::Result<()> {
+    let env = &session.env;
+    let bundle = SessionBundle {
+        format: FORMAT.to_string(),
+        version: VERSION,
+        name: session.name.clone(),
+        exported_at: Local::now().to_rfc3339(),
+        cwd: env.tilde_path(&env.current_dir),
+        previous_dir: env.previous_dir.as_ref().map(|dir| env.tilde_path(dir)),
+        dir_stack: env.dir_stack.iter().map(|dir| env.tilde_path(dir)).collect(),
+        vars: env.vars.clone(),
+        aliases: env.aliases.clone(),
+        history: session.history.clone(),
+        voia_persona: session.voia_persona.clone(),
+        voia: session.voia.as_ref().map(|voia| VoiaBundle {
+            model: voia.model.clone(),
+            conversation: voia.conversation_history.clone(),
+        }),
+    };
+    fs::write(target, serde_json::to_string_pretty(&bundle)? + "\n")?;
+    writeln!(out, "{} {} to {}", "Exported session".green(), session.name.yellow(), target.display())
+}
+
+pub fn read(source: &Path) -> io::Result<SessionBundle> {
+    let bundle: SessionBundle = serde_json::from_str(&fs::read_to_string(source)?)?;
+    if bundle.format != FORMAT || bundle.version > VERSION {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} isn't a session bundle this version can read", source.display())));
+    }
+    Ok(bundle)
+}
+
+// Builds a new session called `name` from a bundle. Directories that don't exist on this
+// machine are skipped with a warning.
+pub fn restore(bundle: SessionBundle, name: String, out: &mut impl Write) -> io::Result<Session> {
+    let mut session = Session::new(name);
+    let env = &mut session.env;
+    let existing = |dir: &String| Some(env.resolve_path(dir)).filter(|path| path.is_dir());
+    let previous_dir = bundle.previous_dir.as_ref().and_then(existing);
+    let dir_stack: Vec<PathBuf> = bundle.dir_stack.iter().filter_map(existing).collect();
+    let skipped = bundle.dir_stack.len() - dir_stack.len();
+    match existing(&bundle.cwd) {
+        // The manager moves the process there when it switches to the session
+        Some(cwd) => env.current_dir = cwd,
+        None => writeln!(out, "{}", format!("Starting in {}: {} doesn't exist here", env.get_current_dir_display(), bundle.cwd).yellow())?,
+    }
+    env.previous_dir = previous_dir;
+    env.dir_stack = dir_stack;
+    if skipped > 0 {
+        writeln!(out, "{}", format!("Skipped stacked directories that don't exist here: {}", skipped).yellow())?;
+    }
+
+    env.vars = bundle.vars;
+    env.aliases = bundle.aliases;
+    session.history = bundle.history.clone();
+    session.editor_history = bundle.history;
+    session.voia_persona = bundle.voia_persona;
+    if let Some(bundled) = bundle.voia {
+        // Voia needs this machine's API key to start
+        if std::env::var("OPENAI_API_KEY").is_ok() {
+            let mut voia = Voia::new(session.voia_persona.clone());
+            voia.set_model(&bundled.model);
+            voia.conversation_history = bundled.conversation;
+            session.voia = Some(voia);
+        } else {
+            writeln!(out, "{}", "Voia conversation not restored: OPENAI_API_KEY isn't set".yellow())?;
+        }
+    }
+
+    writeln!(out, "{} {} as {} ({} history entries, {} stacked directories)", "Imported session".green(),
+        bundle.name.yellow(), session.name.yellow(), session.history.len(), session.env.dir_stack.len())?;
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn export_then_import_gives_the_same_session() {
+        let dir = std::env::temp_dir().join(format!("partermai-bundle-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        let mut session = Session::new("work".to_string());
+        session.env.current_dir = dir.join("sub");
+        session.env.previous_dir = Some(dir.clone());
+        session.env.dir_stack = vec![dir.clone(), dir.join("gone")];
+        session.env.vars.insert("API_URL".to_string(), "http://localhost".to_string());
+        session.env.aliases.insert("ll".to_string(), "ls -l".to_string());
+        session.history = vec!["ls".to_string(), "cd sub".to_string()];
+        session.voia_persona = Some("terse".to_string());
+
+        let file = dir.join("work.json");
+        export(&session, &file, &mut Vec::new()).unwrap();
+        let bundle = read(&file).unwrap();
+        assert_eq!(bundle.name, "work");
+        let restored = restore(bundle, "work-2".to_string(), &mut Vec::new()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(restored.name, "work-2");
+        assert_eq!(restored.env.current_dir, dir.join("sub"));
+        assert_eq!(restored.env.previous_dir, Some(dir.clone()));
+        // Directories missing on this machine are left out
+        assert_eq!(restored.env.dir_stack, vec![dir]);
+        assert_eq!(restored.env.vars, session.env.vars);
+        assert_eq!(restored.env.aliases, session.env.aliases);
+        assert_eq!(restored.history, session.history);
+        assert_eq!(restored.editor_history, session.history);
+        assert_eq!(restored.voia_persona, session.voia_persona);
+    }
+
+    #[test]
+    fn read_rejects_other_json() {
+        let file = std::env::temp_dir().join(format!("partermai-not-a-bundle-{}.json", std::process::id()));
+        fs::write(&file, r#"{"format": "something-else", "version": 1, "name": "x", "exported_at": "", "cwd": "~"}"#).unwrap();
+        let result = read(&file);
+        fs::remove_file(&file).unwrap();
+        assert_eq!(result.err().map(|e| e.kind()), Some(io::ErrorKind::InvalidData));
+    }
+}
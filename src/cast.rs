@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+#[cfg(unix)]
+use std::io::{BufWriter, IsTerminal};
+#[cfg(unix)]
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use std::sync::{Mutex, MutexGuard};
+#[cfg(unix)]
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::Local;
+use colored::*;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use crate::pty::check;
+use crate::Environment;
+
+const MAX_SPEED: f64 = 64.0;
+// How often a paused or slow replay looks at the keyboard
+const KEY_CHECK: Duration = Duration::from_millis(100);
+#[cfg(unix)]
+const POLL_INTERVAL_MS: i32 = 50;
+#[cfg(unix)]
+const BUFFER_SIZE: usize = 8192;
+
+// The first line of an asciicast v2 file; events follow, one JSON array per line
+#[derive(Serialize, Deserialize)]
+struct Header {
+    version: u8,
+    width: u16,
+    height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+}
+
+// The text in `bytes`, keeping a character split across reads in `tail` for next time
+#[cfg(unix)]
+fn decode(tail: &mut Vec<u8>, bytes: &[u8]) -> String {
+    tail.extend_from_slice(bytes);
+    let complete = match std::str::from_utf8(tail) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => tail.len(),
+    };
+    let text = String::from_utf8_lossy(&tail[..complete]).into_owned();
+    tail.drain(..complete);
+    text
+}
+
+#[cfg(unix)]
+struct Recorder {
+    file: BufWriter<File>,
+    started: Instant,
+    stdin: bool, // Keystrokes are only kept when asked for, since they include passwords
+    input_tail: Vec<u8>,
+    output_tail: Vec<u8>,
+}
+
+#[cfg(unix)]
+impl Recorder {
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+        let time = (self.started.elapsed().as_secs_f64() * 1e6).round() / 1e6;
+        writeln!(self.file, "{}", serde_json::to_string(&(time, kind, data))?)?;
+        self.file.flush()
+    }
+
+    fn input(&mut self, bytes: &[u8]) -> io::Result<()> {
+        if !self.stdin {
+            return Ok(());
+        }
+        let text = decode(&mut self.input_tail, bytes);
+        self.event("i", &text)
+    }
+
+    fn output(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let text = decode(&mut self.output_tail, bytes);
+        self.event("o", &text)
+    }
+
+    fn resize(&mut self, rows: u16, cols: u16) -> io::Result<()> {
+        self.event("r", &format!("{}x{}", cols, rows))
+    }
+}
+
+// While recording, Partermai's stdin, stdout and stderr are a pty, and a thread passes
+// everything between it and the real terminal, writing it down on the way
+#[cfg(unix)]
+struct Recording {
+    path: PathBuf,
+    terminal: [OwnedFd; 3],   // The real stdin, stdout and stderr
+    settings: libc::termios,  // The real terminal's settings from before the recording
+    stop: UnixStream,         // Written to when the relay should finish
+    relay: thread::JoinHandle<io::Result<()>>,
+}
+
+#[cfg(unix)]
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+#[cfg(unix)]
+fn recording() -> io::Result<MutexGuard<'static, Option<Recording>>> {
+    RECORDING.lock().map_err(|_| io::Error::other("The recording was interrupted"))
+}
+
+#[cfg(unix)]
+fn read_fd(fd: &impl AsRawFd, buf: &mut [u8]) -> io::Result<usize> {
+    // SAFETY: `buf` is valid for buf.len() bytes
+    let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+}
+
+#[cfg(unix)]
+fn write_fd(fd: &impl AsRawFd, bytes: &[u8]) -> io::Result<usize> {
+    // SAFETY: `bytes` is valid for bytes.len() bytes
+    let n = unsafe { libc::write(fd.as_raw_fd(), bytes.as_ptr().cast(), bytes.len()) };
+    if n < 0 { Err(io::Error::last_os_error()) } else { Ok(n as usize) }
+}
+
+#[cfg(unix)]
+fn write_all_fd(fd: &impl AsRawFd, mut bytes: &[u8]) -> io::Result<()> {
+    while !bytes.is_empty() {
+        match write_fd(fd, bytes) {
+            Ok(n) => bytes = &bytes[n..],
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Passes output from the pty to the terminal and keystrokes the other way until `stop`
+// is written to. Keystrokes the session isn't reading yet wait in `pending`, so a big paste
+// can't stop its output from getting through.
+#[cfg(unix)]
+fn relay(master: OwnedFd, input: OwnedFd, output: OwnedFd, stop: UnixStream, mut recorder: Recorder) -> io::Result<()> {
+    let mut buf = [0u8; BUFFER_SIZE];
+    let mut pending: Vec<u8> = Vec::new();
+    let mut size = terminal::size()?;
+    let mut stopping = false;
+    loop {
+        let mut fds = [
+            libc::pollfd { fd: master.as_raw_fd(), events: libc::POLLIN | if pending.is_empty() { 0 } else { libc::POLLOUT }, revents: 0 },
+            libc::pollfd { fd: input.as_raw_fd(), events: if stopping { 0 } else { libc::POLLIN }, revents: 0 },
+            libc::pollfd { fd: stop.as_raw_fd(), events: if stopping { 0 } else { libc::POLLIN }, revents: 0 },
+        ];
+        // SAFETY: `fds` is a valid array of three pollfds
+        let ready = unsafe { libc::poll(fds.as_mut_ptr(), 3, POLL_INTERVAL_MS) };
+        if ready == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        // Once stopping, whatever the session printed last is copied out before finishing
+        if stopping && ready == 0 {
+            return recorder.file.flush();
+        }
+
+        if fds[0].revents & libc::POLLOUT != 0 {
+            match write_fd(&master, &pending) {
+                Ok(n) => drop(pending.drain(..n)),
+                Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock) => {}
+                Err(_) => pending.clear(),
+            }
+        }
+        if fds[0].revents & !libc::POLLOUT != 0 {
+            match read_fd(&master, &mut buf) {
+                Ok(n) if n > 0 => {
+                    write_all_fd(&output, &buf[..n])?;
+                    recorder.output(&buf[..n])?;
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock) => {}
+                // EOF or EIO: nothing has the pty open any more
+                _ => return recorder.file.flush(),
+            }
+        }
+        if fds[1].revents != 0 {
+            match read_fd(&input, &mut buf) {
+                Ok(n) if n > 0 => {
+                    crate::timing::note_input();
+                    recorder.input(&buf[..n])?;
+                    pending.extend_from_slice(&buf[..n]);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // The terminal went away; keep copying output until told to stop
+                _ => fds[1].events = 0,
+            }
+        }
+        if fds[2].revents != 0 {
+            stopping = true;
+        }
+
+        let new_size = terminal::size()?;
+        if new_size != size {
+            size = new_size;
+            let (cols, rows) = size;
+            crate::pty::resize_pty(&master, rows, cols);
+            recorder.resize(rows, cols)?;
+            // The pty isn't Partermai's controlling terminal, so the kernel doesn't tell it
+            // SAFETY: SIGWINCH is ignored unless something like the line editor handles it
+            unsafe { libc::kill(libc::getpid(), libc::SIGWINCH) };
+        }
+    }
+}
+
+// Puts Partermai on a fresh pty and records everything that goes through it to `path`
+// until `partermai record stop`. The session carries on as it was, in this process.
+#[cfg(unix)]
+fn record(path: PathBuf, session: &str, stdin: bool, out: &mut impl Write) -> io::Result<()> {
+    let mut recording = recording()?;
+    if let Some(current) = recording.as_ref() {
+        return Err(io::Error::other(format!("Already recording to {}", current.path.display())));
+    }
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("Recording needs a terminal"));
+    }
+    let (cols, rows) = terminal::size()?;
+    let header = Header {
+        version: 2,
+        width: cols,
+        height: rows,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs()),
+        idle_time_limit: None,
+        title: Some(format!("Partermai session {}", session)),
+        env: env::var("TERM").into_iter().map(|term| ("TERM".to_string(), term)).collect(),
+    };
+    let mut file = BufWriter::new(File::create(&path)?);
+    writeln!(file, "{}", serde_json::to_string(&header)?)?;
+    writeln!(out, "{} {}; {} finishes", "Recording to".green(), path.display(), "partermai record stop".yellow())?;
+    out.flush()?;
+
+    let (master, slave) = crate::pty::open_sized(rows, cols)?;
+    let terminal = [
+        io::stdin().as_fd().try_clone_to_owned()?,
+        io::stdout().as_fd().try_clone_to_owned()?,
+        io::stderr().as_fd().try_clone_to_owned()?,
+    ];
+    let relay_ends = (terminal[0].try_clone()?, terminal[1].try_clone()?);
+    let (stop, stop_seen) = UnixStream::pair()?;
+    // SAFETY: the termios calls only read and write the structs passed to them, on
+    // descriptors we own; dup2 replaces our own stdio with the pty
+    let settings = unsafe {
+        let mut settings = std::mem::zeroed::<libc::termios>();
+        check(libc::tcgetattr(terminal[0].as_raw_fd(), &mut settings))?;
+        // Programs see the line settings they had, and the real terminal passes
+        // everything through untouched
+        check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &settings))?;
+        let mut raw = settings;
+        libc::cfmakeraw(&mut raw);
+        check(libc::tcsetattr(terminal[0].as_raw_fd(), libc::TCSANOW, &raw))?;
+        check(libc::fcntl(master.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK))?;
+        for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            check(libc::dup2(slave.as_raw_fd(), fd))?;
+        }
+        settings
+    };
+    drop(slave);
+
+    let recorder = Recorder { file, started: Instant::now(), stdin, input_tail: Vec::new(), output_tail: Vec::new() };
+    let relay = thread::spawn(move || relay(master, relay_ends.0, relay_ends.1, stop_seen, recorder));
+    *recording = Some(Recording { path, terminal, settings, stop, relay });
+    Ok(())
+}
+
+// Ends the recording, if there is one, and gives Partermai its terminal back. Returns
+// where the recording went.
+#[cfg(unix)]
+pub fn stop_recording() -> io::Result<Option<PathBuf>> {
+    let Some(mut recording) = recording()?.take() else { return Ok(None) };
+    io::stdout().flush()?;
+    io::stderr().flush()?;
+    recording.stop.write_all(b"\n")?;
+    let relayed = recording.relay.join().unwrap_or_else(|_| Err(io::Error::other("The recording stopped unexpectedly")));
+    // SAFETY: dup2 and tcsetattr only use descriptors we own
+    unsafe {
+        for (fd, saved) in recording.terminal.iter().enumerate() {
+            check(libc::dup2(saved.as_raw_fd(), fd as libc::c_int))?;
+        }
+        check(libc::tcsetattr(recording.terminal[0].as_raw_fd(), libc::TCSANOW, &recording.settings))?;
+    }
+    relayed?;
+    Ok(Some(recording.path))
+}
+
+#[cfg(not(unix))]
+fn record(_: PathBuf, _: &str, _: bool, _: &mut impl Write) -> io::Result<()> {
+    Err(io::Error::other("Recording needs a Unix pseudo-terminal"))
+}
+
+#[cfg(not(unix))]
+pub fn stop_recording() -> io::Result<Option<PathBuf>> {
+    Ok(None)
+}
+
+// Waits `wait` seconds of recording time, letting the keys pause (space), change the
+// speed (+ and -) or stop the replay (q). Returns false when stopped.
+fn wait_for_frame(mut wait: f64, speed: &mut f64, paused: &mut bool) -> io::Result<bool> {
+    while wait > 0.0 || *paused {
+        let step = if *paused { KEY_CHECK } else { Duration::from_secs_f64(wait / *speed).min(KEY_CHECK) };
+        let started = Instant::now();
+        if event::poll(step)? {
+            if let Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) = event::read()? {
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+                    KeyCode::Char(' ') => *paused = !*paused,
+                    KeyCode::Char('+' | '=') => *speed = (*speed * 2.0).min(MAX_SPEED),
+                    KeyCode::Char('-') => *speed = (*speed / 2.0).max(1.0 / MAX_SPEED),
+                    // While paused, . shows the next frame
+                    KeyCode::Char('.') if *paused => return Ok(true),
+                    _ => {}
+                }
+            }
+        }
+        if !*paused {
+            wait -= started.elapsed().as_secs_f64() * *speed;
+        }
+    }
+    Ok(true)
+}
+
+// Plays the output of an asciicast v2 recording back with its timing, `speed` times as fast
+fn replay(path: &Path, mut speed: f64, out: &mut impl Write) -> io::Result<()> {
+    let mut lines = BufReader::new(File::open(path)?).lines();
+    let first = lines.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty recording"))??;
+    let header: Header = serde_json::from_str(&first)?;
+    if header.version != 2 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("asciicast version {} isn't supported", header.version)));
+    }
+    // Input and resize events aren't shown, only what the terminal displayed
+    let mut frames = Vec::new();
+    for line in lines {
+        if let Ok((time, kind, data)) = serde_json::from_str::<(f64, String, String)>(&line?) {
+            if kind == "o" {
+                frames.push((time, data));
+            }
+        }
+    }
+
+    let (cols, rows) = terminal::size().unwrap_or((80, 24));
+    if header.width > cols || header.height > rows {
+        writeln!(out, "{}", format!("Recorded at {}x{}, but this terminal is {}x{}; it may look off",
+            header.width, header.height, cols, rows).yellow())?;
+    }
+    writeln!(out, "{}", "space pause, . step, + faster, - slower, q stop".dimmed())?;
+    out.flush()?;
+
+    terminal::enable_raw_mode()?;
+    let mut paused = false;
+    let mut previous = 0.0;
+    let mut result = Ok(true);
+    for (time, data) in frames {
+        let mut wait = (time - previous).max(0.0);
+        if let Some(limit) = header.idle_time_limit {
+            wait = wait.min(limit);
+        }
+        previous = time;
+        result = wait_for_frame(wait, &mut speed, &mut paused);
+        if !matches!(result, Ok(true)) {
+            break;
+        }
+        if let Err(e) = out.write_all(data.as_bytes()).and_then(|_| out.flush()) {
+            result = Err(e);
+            break;
+        }
+    }
+    terminal::disable_raw_mode()?;
+    write!(out, "\x1B[0m")?;
+    let finished = result?;
+    writeln!(out, "\n{}", if finished { "Replay finished" } else { "Replay stopped" }.dimmed())
+}
+
+// `partermai record start [--stdin] [file]`, `partermai record stop` and
+// `partermai replay <file> [-s speed]`
+pub fn execute_cast_command(args: &[&str], session: &str, env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    match args {
+        ["record", "start", rest @ ..] => {
+            let stdin = rest.contains(&"--stdin");
+            let path = match rest.iter().filter(|arg| **arg != "--stdin").collect::<Vec<_>>().as_slice() {
+                [] => env.current_dir.join(format!("{}-{}.cast", session, Local::now().format("%Y%m%d-%H%M%S"))),
+                [file] => env.resolve_path(file),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: partermai record start [--stdin] [file]")),
+            };
+            record(path, session, stdin, out)
+        }
+        ["record", "stop"] => match stop_recording()? {
+            Some(path) => writeln!(out, "{} {}", "Recording saved to".green(), path.display()),
+            None => Err(io::Error::other("Not recording; start with 'partermai record start [file]'")),
+        },
+        ["replay", file, options @ ..] => {
+            let speed = match options {
+                [] => 1.0,
+                ["-s" | "--speed", speed] => speed.parse::<f64>().ok()
+                    .filter(|speed| *speed > 0.0)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid speed: {}", speed)))?,
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: partermai replay <file> [-s speed]")),
+            };
+            replay(&env.resolve_path(file), speed, out)
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: partermai record start [--stdin] [file] | record stop | replay <file> [-s speed]")),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_holds_back_a_split_character() {
+        let mut tail = Vec::new();
+        let bytes = "aé".as_bytes();
+        assert_eq!(decode(&mut tail, &bytes[..2]), "a");
+        assert_eq!(tail, vec![0xC3]);
+        assert_eq!(decode(&mut tail, &bytes[2..]), "é");
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn decode_replaces_invalid_bytes() {
+        let mut tail = Vec::new();
+        assert_eq!(decode(&mut tail, b"a\xFFb"), "a\u{FFFD}b");
+        assert!(tail.is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::Path;
+use colored::*;
+use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
+use syntect::parsing::{SyntaxReference, SyntaxSet};
+use syntect::util::as_24_bit_terminal_escaped;
+use crate::config::CatConfig;
+use crate::Environment;
+
+// Files are streamed in chunks of this size; lines longer than this are split
+const CHUNK_SIZE: usize = 64 * 1024;
+// How much of a file is inspected when deciding whether it is binary
+const BINARY_SNIFF_LEN: usize = 8 * 1024;
+
+#[derive(Default)]
+pub struct CatOptions {
+    pub number_lines: bool, // -n
+    pub plain: bool,        // -p: never highlight
+    pub hexdump: bool,      // -x
+}
+
+// Same heuristic as git and grep: a NUL byte near the start means binary
+fn is_binary(sample: &[u8]) -> bool {
+    sample.contains(&0)
+}
+
+// Picks a syntax from the file extension, then from a shebang or modeline
+fn detect_syntax<'a>(ps: &'a SyntaxSet, path: &Path, first_line: &str) -> Option<&'a SyntaxReference> {
+    let by_extension = path.extension()
+        .and_then(|ext| ps.find_syntax_by_extension(&ext.to_string_lossy()))
+        .or_else(|| path.file_name().and_then(|name| ps.find_syntax_by_extension(&name.to_string_lossy())));
+    by_extension
+        .or_else(|| ps.find_syntax_by_first_line(first_line))
+        .filter(|syntax| syntax.name != "Plain Text")
+}
+
+// Reads up to and including the next newline, but never more than CHUNK_SIZE bytes
+fn read_chunk(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<usize> {
+    buf.clear();
+    reader.take(CHUNK_SIZE as u64).read_until(b'\n', buf)
+}
+
+fn line_prefix(number_lines: bool, line_no: &mut usize) -> String {
+    if !number_lines {
+        return String::new();
+    }
+    *line_no += 1;
+    format!("{:>6}  ", line_no).dimmed().to_string()
+}
+
+// Classic 16-bytes-per-row dump: offset, hex bytes, printable ASCII
+fn write_hexdump(reader: &mut impl Read, out: &mut impl Write) -> io::Result<()> {
+    let mut row = [0u8; 16];
+    let mut offset = 0usize;
+
+    loop {
+        let mut filled = 0;
+        while filled < row.len() {
+            match reader.read(&mut row[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            break;
+        }
+
+        let bytes = &row[..filled];
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = bytes.iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        let (left, right) = hex.split_at(hex.len().min(8));
+        writeln!(out, "{}  {:<23}  {:<23}  |{}|",
+            format!("{:08x}", offset).dimmed(), left.join(" "), right.join(" "), ascii)?;
+
+        offset += filled;
+        if filled < row.len() {
+            break;
+        }
+    }
+    writeln!(out, "{}", format!("{:08x}", offset).dimmed())
+}
+
+pub fn execute_cat(
+    paths: &[&str],
+    env: &Environment,
+    options: &CatOptions,
+    config: &CatConfig,
+    ps: &SyntaxSet,
+    ts: &ThemeSet,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let is_tty = io::stdout().is_terminal();
+    let highlight = !options.plain && is_tty;
+    let theme = ts.themes.get(&config.theme)
+        .unwrap_or_else(|| &ts.themes["base16-ocean.dark"]);
+    // Numbering carries across files, like GNU cat
+    let mut line_no = 0;
+
+    for path in paths {
+        let target_path = env.resolve_path(path);
+        let file = File::open(&target_path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path, e)))?;
+        let mut reader = BufReader::with_capacity(CHUNK_SIZE, file);
+
+        if options.hexdump {
+            write_hexdump(&mut reader, out)?;
+            continue;
+        }
+
+        let sample = reader.fill_buf()?;
+        if is_binary(&sample[..sample.len().min(BINARY_SNIFF_LEN)]) {
+            if is_tty {
+                writeln!(out, "{}: {} {}", path.yellow(), "binary file not shown.".yellow(),
+                    "Use 'cat -x' for a hexdump.".dimmed())?;
+            } else {
+                // Piped binary output is passed through untouched
+                io::copy(&mut reader, out)?;
+            }
+            continue;
+        }
+
+        if !is_tty && !options.number_lines {
+            io::copy(&mut reader, out)?;
+            continue;
+        }
+
+        let mut buf = Vec::with_capacity(CHUNK_SIZE);
+        read_chunk(&mut reader, &mut buf)?;
+        let first_line = String::from_utf8_lossy(&buf).into_owned();
+        let mut highlighter = if highlight {
+            detect_syntax(ps, &target_path, &first_line).map(|syntax| HighlightLines::new(syntax, theme))
+        } else {
+            None
+        };
+
+        let mut at_line_start = true;
+        while !buf.is_empty() {
+            // Invalid UTF-8 shows up as U+FFFD instead of aborting the whole file
+            let line = String::from_utf8_lossy(&buf);
+            if at_line_start {
+                write!(out, "{}", line_prefix(options.number_lines, &mut line_no))?;
+            }
+            at_line_start = buf.ends_with(b"\n");
+            match highlighter.as_mut() {
+                Some(h) => {
+                    let ranges = h.highlight_line(&line, ps).map_err(io::Error::other)?;
+                    write!(out, "{}\x1B[0m", as_24_bit_terminal_escaped(&ranges[..], false))?;
+                }
+                None => write!(out, "{}", line)?,
+            }
+            read_chunk(&mut reader, &mut buf)?;
+        }
+    }
+    out.flush()
+}
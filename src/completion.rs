@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper, Result};
+
+// Every command handled by Partermai itself rather than the host system
+pub const BUILTINS: &[&str] = &[
+    "exit", "quit", "clear", "tip", "history", "ls", "tree", "cd", "pushd", "popd", "dirs",
+    "z", "j", "mark", "jump", "marks", "unmark", "pwd", "time", "type", "which", "command", "cat",
+    "find", "ff", "grep", "head", "tail", "wc", "sort", "uniq", "mkdir", "touch", "cp", "mv",
+    "rm", "trash", "undo", "voia", "voia-clear", "voia-setkey", "voia-model", "partermai",
+];
+
+// Commands whose first argument is a bookmark name
+const BOOKMARK_COMMANDS: &[&str] = &["jump", "unmark"];
+
+// Tab completion for builtins, bookmarks (`jump work`, `@work/src`) and file names
+pub struct ShellHelper {
+    bookmarks: BTreeMap<String, PathBuf>,
+    filenames: FilenameCompleter,
+}
+
+impl ShellHelper {
+    pub fn new(bookmarks: &BTreeMap<String, PathBuf>) -> Self {
+        Self {
+            bookmarks: bookmarks.clone(),
+            filenames: FilenameCompleter::new(),
+        }
+    }
+
+    pub fn set_bookmarks(&mut self, bookmarks: &BTreeMap<String, PathBuf>) {
+        self.bookmarks = bookmarks.clone();
+    }
+
+    fn complete_bookmark_names(&self, prefix: &str, decorate: impl Fn(&str) -> String) -> Vec<Pair> {
+        self.bookmarks.keys()
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| Pair { display: name.clone(), replacement: decorate(name) })
+            .collect()
+    }
+
+    // Completes `@name/sub/pa` against the files below the bookmarked directory
+    fn complete_bookmark_path(&self, word: &str) -> Vec<Pair> {
+        let Some((name, rest)) = word[1..].split_once('/') else { return Vec::new() };
+        let Some(base) = self.bookmarks.get(name) else { return Vec::new() };
+        let (dir_part, file_prefix) = match rest.rfind('/') {
+            Some(i) => (&rest[..=i], &rest[i + 1..]),
+            None => ("", rest),
+        };
+
+        let Ok(entries) = fs::read_dir(base.join(dir_part)) else { return Vec::new() };
+        let mut pairs: Vec<Pair> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if !file_name.starts_with(file_prefix) || (file_prefix.is_empty() && file_name.starts_with('.')) {
+                    return None;
+                }
+                let suffix = if entry.path().is_dir() { "/" } else { "" };
+                Some(Pair {
+                    display: format!("{}{}", file_name, suffix),
+                    replacement: format!("@{}/{}{}{}", name, dir_part, file_name, suffix),
+                })
+            })
+            .collect();
+        pairs.sort_by(|a, b| a.display.cmp(&b.display));
+        pairs
+    }
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        let before = &line[..pos];
+        let start = before.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &before[start..];
+        let preceding: Vec<&str> = before[..start].split_whitespace().collect();
+
+        if preceding.is_empty() {
+            let commands = BUILTINS.iter()
+                .filter(|cmd| cmd.starts_with(word))
+                .map(|cmd| Pair { display: cmd.to_string(), replacement: format!("{} ", cmd) })
+                .collect();
+            return Ok((start, commands));
+        }
+
+        if let Some(name) = word.strip_prefix('@') {
+            if name.contains('/') {
+                return Ok((start, self.complete_bookmark_path(word)));
+            }
+            return Ok((start, self.complete_bookmark_names(name, |name| format!("@{}/", name))));
+        }
+
+        if preceding.len() == 1 && BOOKMARK_COMMANDS.contains(&preceding[0]) {
+            return Ok((start, self.complete_bookmark_names(word, |name| name.to_string())));
+        }
+
+        self.filenames.complete(line, pos, ctx)
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
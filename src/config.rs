@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use colored::*;
+use serde::{Deserialize, Serialize};
+
+// User configuration, stored as JSON in the Partermai config directory
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub ls: LsConfig,
+    pub cat: CatConfig,
+    pub pager: PagerConfig,
+    pub correction: CorrectionConfig,
+    pub timing: TimingConfig,
+    pub bookmarks: BTreeMap<String, PathBuf>, // Named directories for mark/jump and @name paths
+    pub profiles: BTreeMap<String, ProfileConfig>, // Presets for `partermai new <name> --profile <profile>`
+    #[serde(skip)]
+    load_error: Option<String>, // Why the file on disk couldn't be read; saving would overwrite it
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LsConfig {
+    pub icons: bool, // Show Nerd Font icons next to entries
+    pub git: bool,   // Always annotate entries with their git status
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CatConfig {
+    pub theme: String, // syntect theme used for highlighting
+}
+
+impl Default for CatConfig {
+    fn default() -> Self {
+        Self {
+            theme: String::from("base16-ocean.dark"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PagerConfig {
+    pub enabled: bool,      // Page output that doesn't fit on the screen
+    pub use_external: bool, // Use $PAGER (or `less -R`) instead of the built-in pager
+}
+
+impl Default for PagerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            use_external: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrectionConfig {
+    pub prompt: bool, // Offer to run the corrected command when a typo has one clear fix
+}
+
+impl Default for CorrectionConfig {
+    fn default() -> Self {
+        Self { prompt: true }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimingConfig {
+    pub threshold_secs: f64, // Print "took 12.3s" after commands at least this long; 0 turns it off
+    pub notify_secs: f64,    // Bell and OSC 9 notification after commands this long you didn't type into; 0 turns it off
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            threshold_secs: 5.0,
+            notify_secs: 30.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub cwd: Option<String>,               // Starting directory; ~ is the home directory
+    pub env: BTreeMap<String, String>,     // Environment variables for the programs the session runs
+    pub aliases: BTreeMap<String, String>, // Command names that stand for longer command lines
+    pub startup: Vec<String>,              // Commands run when the session is first shown
+    pub voia_persona: Option<String>,      // How Voia should behave, given before every conversation
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("partermai").join("config.json"))
+    }
+
+    // Falls back to the defaults when the file is missing. A malformed file is reported and
+    // the defaults used for now, but it is left for the user to fix rather than saved over.
+    pub fn load() -> Self {
+        let Some(path) = Self::path() else { return Self::default() };
+        let Ok(content) = fs::read_to_string(&path) else { return Self::default() };
+        serde_json::from_str(&content).unwrap_or_else(|e| {
+            let error = format!("{}: {}", path.display(), e);
+            println!("{}", format!("Using default settings; couldn't read {}", error).yellow());
+            Self { load_error: Some(error), ..Self::default() }
+        })
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(error) = &self.load_error {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("Not saving over the config file until it is fixed ({})", error)));
+        }
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No config directory"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
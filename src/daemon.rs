@@ -0,0 +1,643 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use colored::*;
+use crossterm::cursor::Show;
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::execute;
+use serde::{Deserialize, Serialize};
+use crate::mux::{session_command, Replies};
+use crate::pty::{self, PtyChild};
+use crate::timing::try_wait_child;
+use crate::{Environment, Session};
+
+// Ctrl+B then d detaches, as in the multiplexer
+const PREFIX: u8 = 0x02;
+// Set in the sessions the daemon runs, to the session's name
+const SESSION_VAR: &str = "PARTERMAI_DAEMON_SESSION";
+const POLL_INTERVAL_MS: i32 = 50;
+const BUFFER_SIZE: usize = 8192;
+const START_TIMEOUT: Duration = Duration::from_secs(3);
+// A client that stops reading for this long is dropped rather than holding up every session
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+
+// After an attach both sides send frames: [kind][payload length, u32 big-endian][payload]
+const INPUT: u8 = 0; // Keystrokes for the session
+const RESIZE: u8 = 1; // The client's terminal size: rows then cols, u16 big-endian
+const OUTPUT: u8 = 2; // What the session printed
+const END: u8 = 3; // The attach is over; the payload says why
+
+// The line a client starts with
+#[derive(Serialize, Deserialize)]
+enum Request {
+    // `bundle` hands over a session that was open in the client, to be started from it
+    Attach { session: String, profile: Option<String>, bundle: Option<PathBuf>, cwd: PathBuf, rows: u16, cols: u16, term: Option<String> },
+    Detach { session: String },
+    List,
+    Stop,
+}
+
+fn current_uid() -> libc::uid_t {
+    // SAFETY: getuid always succeeds
+    unsafe { libc::getuid() }
+}
+
+// The socket lives in a directory only we can use. Without XDG_RUNTIME_DIR that directory
+// is in /tmp under a name anyone can guess, so one made by another user is refused rather
+// than trusted with our keystrokes.
+fn socket_path() -> io::Result<PathBuf> {
+    let uid = current_uid();
+    let dir = dirs::runtime_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("partermai-{}", uid));
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    let metadata = fs::symlink_metadata(&dir)?;
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied,
+            format!("{} must be a directory owned by you with mode 700", dir.display())));
+    }
+    Ok(dir.join("daemon.sock"))
+}
+
+// Where a session being handed to the daemon is written, next to the socket
+fn handover_path(session: &str) -> io::Result<PathBuf> {
+    let dir = socket_path()?.with_file_name("");
+    Ok(dir.join(format!("handover-{}-{}.json", std::process::id(), session)))
+}
+
+// The user on the other end of a connection
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` describe a buffer the size of a ucred
+    let result = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, std::ptr::addr_of_mut!(cred).cast(), &mut len)
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: getpeereid only writes the two ids
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(uid)
+}
+
+fn same_user(stream: &UnixStream) -> bool {
+    peer_uid(stream).is_ok_and(|uid| uid == current_uid())
+}
+
+// A connection to the daemon, if it is running as us
+fn connect() -> io::Result<Option<UnixStream>> {
+    let Ok(stream) = UnixStream::connect(socket_path()?) else { return Ok(None) };
+    if !same_user(&stream) {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "The daemon's socket belongs to another user"));
+    }
+    Ok(Some(stream))
+}
+
+fn write_frame(out: &mut impl Write, kind: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.push(kind);
+    frame.extend((payload.len() as u32).to_be_bytes());
+    frame.extend(payload);
+    out.write_all(&frame)
+}
+
+// The next complete frame in `buffer`, if one has arrived
+fn take_frame(buffer: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    let len = u32::from_be_bytes(buffer.get(1..5)?.try_into().ok()?) as usize;
+    let payload = buffer.get(5..len + 5)?.to_vec();
+    let kind = buffer[0];
+    buffer.drain(..len + 5);
+    Some((kind, payload))
+}
+
+fn send_request(stream: &mut UnixStream, request: &Request) -> io::Result<()> {
+    writeln!(stream, "{}", serde_json::to_string(request)?)
+}
+
+struct DaemonSession {
+    pty: PtyChild,
+    screen: vt100::Parser<Replies>, // Kept up to date so a new client can be shown the screen
+}
+
+impl DaemonSession {
+    fn resize(&mut self, rows: u16, cols: u16) {
+        if self.screen.screen().size() != (rows, cols) {
+            self.screen.screen_mut().set_size(rows, cols);
+            self.pty.resize(rows, cols);
+        }
+    }
+}
+
+// The Partermai process for a daemon session, started from a profile or from a bundle
+// handed over by the client
+fn session_process(name: &str, profile: Option<&str>, bundle: Option<&Path>, cwd: &Path, term: Option<String>) -> io::Result<Command> {
+    let mut command = session_command(name, profile, cwd)?;
+    command.env(SESSION_VAR, name);
+    if let Some(bundle) = bundle {
+        command.arg("--bundle").arg(bundle);
+    }
+    // The session draws on the terminal of whoever started it
+    if let Some(term) = term {
+        command.env("TERM", term);
+    }
+    Ok(command)
+}
+
+struct Client {
+    stream: UnixStream,
+    buffer: Vec<u8>,
+    session: Option<String>, // Set once the client has attached
+    gone: bool,
+}
+
+struct Daemon {
+    listener: UnixListener,
+    sessions: BTreeMap<String, DaemonSession>,
+    clients: Vec<Client>,
+    stopping: bool,
+}
+
+impl Daemon {
+    fn open_session(&mut self, name: &str, command: Command, rows: u16, cols: u16) -> io::Result<()> {
+        let pty = pty::spawn_sized(command, rows, cols)?;
+        pty.set_nonblocking()?;
+        let session = DaemonSession {
+            pty,
+            screen: vt100::Parser::new_with_callbacks(rows, cols, 0, Replies::default()),
+        };
+        self.sessions.insert(name.to_string(), session);
+        Ok(())
+    }
+
+    // Ends every attach showing `session`
+    fn end_attaches(&mut self, session: &str, reason: &str) {
+        for client in self.clients.iter_mut().filter(|client| client.session.as_deref() == Some(session)) {
+            let _ = write_frame(&mut client.stream, END, reason.as_bytes());
+            client.gone = true;
+        }
+    }
+
+    fn handle_request(&mut self, index: usize, request: Request) {
+        match request {
+            Request::Attach { session, profile, bundle, cwd, rows, cols, term } => {
+                let opened = match (self.sessions.contains_key(&session), &bundle) {
+                    (false, _) => session_process(&session, profile.as_deref(), bundle.as_deref(), &cwd, term)
+                        .and_then(|command| self.open_session(&session, command, rows, cols)),
+                    (true, None) => Ok(()),
+                    (true, Some(_)) => Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("the daemon already has a session named {}", session))),
+                };
+                if let Err(e) = opened {
+                    let client = &mut self.clients[index];
+                    let _ = write_frame(&mut client.stream, END, e.to_string().as_bytes());
+                    client.gone = true;
+                    return;
+                }
+                let (Some(target), client) = (self.sessions.get_mut(&session), &mut self.clients[index]) else { return };
+                // The session takes the size of whoever attached last
+                target.resize(rows, cols);
+                let mut repaint = b"\x1B[H\x1B[2J".to_vec();
+                repaint.extend(target.screen.screen().state_formatted());
+                client.gone = write_frame(&mut client.stream, OUTPUT, &repaint).is_err();
+                client.session = Some(session);
+            }
+            Request::Detach { session } => {
+                self.end_attaches(&session, "detached");
+                self.clients[index].gone = true;
+            }
+            Request::List => {
+                let listing: String = self.sessions.keys()
+                    .map(|name| {
+                        let attached = self.clients.iter().filter(|client| client.session.as_ref() == Some(name)).count();
+                        format!("{}\t{}\n", name, attached)
+                    })
+                    .collect();
+                let client = &mut self.clients[index];
+                let _ = client.stream.write_all(listing.as_bytes());
+                client.gone = true;
+            }
+            Request::Stop => self.stopping = true,
+        }
+    }
+
+    // Passes what a session printed on to the clients showing it
+    fn read_session(&mut self, name: &str, buf: &mut [u8]) -> bool {
+        let Some(session) = self.sessions.get_mut(name) else { return false };
+        match session.pty.master.read(buf) {
+            Ok(n) if n > 0 => {
+                session.screen.process(&buf[..n]);
+                let replies = std::mem::take(session.screen.callbacks_mut());
+                let mut watched = false;
+                for client in self.clients.iter_mut().filter(|client| client.session.as_deref() == Some(name)) {
+                    watched = true;
+                    client.gone |= write_frame(&mut client.stream, OUTPUT, &buf[..n]).is_err();
+                }
+                // An attached terminal answers the program's questions itself; with none,
+                // the daemon does so the program doesn't wait forever
+                if !watched {
+                    session.pty.send(&replies.to_program);
+                }
+                true
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock) => true,
+            // EOF or EIO: nothing has the session's pty open any more
+            _ => false,
+        }
+    }
+
+    // Reads from a client: its request line first, then input and resize frames
+    fn read_client(&mut self, index: usize, buf: &mut [u8]) -> Option<Request> {
+        let client = &mut self.clients[index];
+        match client.stream.read(buf) {
+            Ok(n) if n > 0 => client.buffer.extend(&buf[..n]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => return None,
+            _ => {
+                client.gone = true;
+                return None;
+            }
+        }
+        let Some(name) = client.session.clone() else {
+            let end = client.buffer.iter().position(|byte| *byte == b'\n')?;
+            let line: Vec<u8> = client.buffer.drain(..=end).collect();
+            let request = serde_json::from_slice(&line).ok();
+            client.gone = request.is_none();
+            return request;
+        };
+        let session = self.sessions.get_mut(&name)?;
+        while let Some((kind, payload)) = take_frame(&mut client.buffer) {
+            match (kind, payload.as_slice()) {
+                (INPUT, _) => session.pty.send(&payload),
+                (RESIZE, [r1, r2, c1, c2]) => session.resize(u16::from_be_bytes([*r1, *r2]), u16::from_be_bytes([*c1, *c2])),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn serve_loop(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; BUFFER_SIZE];
+        while !self.stopping {
+            let names: Vec<String> = self.sessions.keys().cloned().collect();
+            let pollin = |fd| libc::pollfd { fd, events: libc::POLLIN, revents: 0 };
+            let mut fds: Vec<libc::pollfd> = std::iter::once(pollin(self.listener.as_raw_fd()))
+                .chain(names.iter().map(|name| {
+                    let pty = &self.sessions[name].pty;
+                    libc::pollfd { fd: pty.master.as_raw_fd(), events: pty.poll_events(), revents: 0 }
+                }))
+                .chain(self.clients.iter().map(|client| pollin(client.stream.as_raw_fd())))
+                .collect();
+            // SAFETY: `fds` is a valid array of fds.len() pollfds
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, POLL_INTERVAL_MS) } == -1 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            let mut ended = Vec::new();
+            for (name, fd) in names.iter().zip(&fds[1..]) {
+                if fd.revents & libc::POLLOUT != 0 {
+                    if let Some(session) = self.sessions.get_mut(name) {
+                        session.pty.flush_input();
+                    }
+                }
+                if fd.revents & !libc::POLLOUT != 0 && !self.read_session(name, &mut buf) {
+                    ended.push(name.clone());
+                }
+            }
+            // A session can end while something it started still holds the pty open
+            ended.extend(names.iter().filter(|name| matches!(try_wait_child(&self.sessions[*name].pty.child), Ok(Some(_)))).cloned());
+            for name in ended {
+                if let Some(mut session) = self.sessions.remove(&name) {
+                    session.pty.kill();
+                }
+                self.end_attaches(&name, "session ended");
+            }
+
+            for (index, fd) in fds[1 + names.len()..].iter().enumerate() {
+                if fd.revents != 0 {
+                    if let Some(request) = self.read_client(index, &mut buf) {
+                        self.handle_request(index, request);
+                    }
+                }
+            }
+            self.clients.retain(|client| !client.gone);
+
+            if fds[0].revents != 0 {
+                // Only our own user gets to see or type into the sessions
+                if let Some((stream, _)) = self.listener.accept().ok().filter(|(stream, _)| same_user(stream)) {
+                    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+                    self.clients.push(Client { stream, buffer: Vec::new(), session: None, gone: false });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The daemon itself, run as `Partermai --daemon`: owns the sessions and serves clients on
+// a Unix socket until stopped
+pub fn serve() -> io::Result<()> {
+    let path = socket_path()?;
+    if UnixStream::connect(&path).is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "The daemon is already running"));
+    }
+    // Left behind by a daemon that didn't get to clean up
+    let _ = fs::remove_file(&path);
+    let mut daemon = Daemon {
+        listener: UnixListener::bind(&path)?,
+        sessions: BTreeMap::new(),
+        clients: Vec::new(),
+        stopping: false,
+    };
+
+    let result = daemon.serve_loop();
+    let names: Vec<String> = daemon.sessions.keys().cloned().collect();
+    for name in names {
+        daemon.end_attaches(&name, "daemon stopped");
+    }
+    for session in daemon.sessions.values_mut() {
+        session.pty.kill();
+    }
+    let _ = fs::remove_file(&path);
+    result
+}
+
+// Starts the daemon in the background, away from this terminal, unless it is running.
+// Returns whether it was started.
+fn start() -> io::Result<bool> {
+    if connect()?.is_some() {
+        return Ok(false);
+    }
+    let mut command = Command::new(env::current_exe()?);
+    // The daemon outlives the pane or terminal it was started from, so it mustn't pass
+    // either on to its sessions
+    command.arg("--daemon")
+        .current_dir("/")
+        .env_remove(crate::mux::NESTED_VAR)
+        .env_remove(SESSION_VAR)
+        .env_remove("TERM")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    // SAFETY: setsid is async-signal-safe
+    unsafe {
+        // A session of its own, so closing the terminal doesn't hang it up
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let mut child = command.spawn()?;
+    let started = Instant::now();
+    while started.elapsed() < START_TIMEOUT {
+        if connect()?.is_some() {
+            return Ok(true);
+        }
+        if let Some(status) = child.try_wait()? {
+            return Err(io::Error::other(format!("The daemon exited ({})", status)));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "The daemon didn't start"))
+}
+
+// How an attach went: why it ended, and whether the session was shown at all
+struct Attached {
+    reason: String,
+    shown: bool,
+}
+
+// Relays this terminal to the attached session until the attach ends
+fn attach_loop(stream: &mut UnixStream, out: &mut impl Write) -> io::Result<Attached> {
+    let mut shown = false;
+    let ended = |reason: &str, shown| Ok(Attached { reason: reason.to_string(), shown });
+    let mut buf = [0u8; BUFFER_SIZE];
+    let mut buffer = Vec::new();
+    let mut prefix = false;
+    let mut size = terminal::size()?;
+    loop {
+        let mut fds = [
+            libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 },
+            libc::pollfd { fd: stream.as_raw_fd(), events: libc::POLLIN, revents: 0 },
+        ];
+        // SAFETY: `fds` is a valid array of two pollfds
+        if unsafe { libc::poll(fds.as_mut_ptr(), 2, POLL_INTERVAL_MS) } == -1 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+
+        if fds[1].revents != 0 {
+            match stream.read(&mut buf) {
+                Ok(n) if n > 0 => buffer.extend(&buf[..n]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                _ => return ended("the daemon went away", shown),
+            }
+            while let Some((kind, payload)) = take_frame(&mut buffer) {
+                match kind {
+                    OUTPUT => {
+                        shown = true;
+                        out.write_all(&payload)?;
+                    }
+                    END => return ended(&String::from_utf8_lossy(&payload), shown),
+                    _ => {}
+                }
+            }
+            out.flush()?;
+        }
+
+        if fds[0].revents != 0 {
+            // SAFETY: `buf` is valid for BUFFER_SIZE bytes
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), BUFFER_SIZE) };
+            if n <= 0 {
+                return ended("detached", shown);
+            }
+            let mut input = Vec::new();
+            for &byte in &buf[..n as usize] {
+                match (prefix, byte) {
+                    (false, PREFIX) => prefix = true,
+                    (false, _) => input.push(byte),
+                    (true, b'd') => {
+                        write_frame(stream, INPUT, &input)?;
+                        return ended("detached", shown);
+                    }
+                    // Ctrl+B twice sends one; before anything else it goes through as typed
+                    (true, _) => {
+                        prefix = false;
+                        if byte != PREFIX {
+                            input.push(PREFIX);
+                        }
+                        input.push(byte);
+                    }
+                }
+            }
+            if !input.is_empty() {
+                write_frame(stream, INPUT, &input)?;
+            }
+        }
+
+        let new_size = terminal::size()?;
+        if new_size != size {
+            size = new_size;
+            let (cols, rows) = size;
+            write_frame(stream, RESIZE, &[rows.to_be_bytes(), cols.to_be_bytes()].concat())?;
+        }
+    }
+}
+
+// Shows a daemon session in this terminal until it is detached or ends, starting the
+// daemon and the session as needed
+fn attach(session: &str, profile: Option<&str>, bundle: Option<PathBuf>, env: &Environment) -> io::Result<Attached> {
+    let mut stream = connect()?.ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "The daemon isn't running"))?;
+    let (cols, rows) = terminal::size()?;
+    send_request(&mut stream, &Request::Attach {
+        session: session.to_string(),
+        profile: profile.map(str::to_string),
+        bundle,
+        cwd: env.current_dir.clone(),
+        rows,
+        cols,
+        term: env::var("TERM").ok(),
+    })?;
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+
+    let result = attach_loop(&mut stream, &mut stdout);
+
+    write!(stdout, "\x1B[?1l\x1B[?2004l")?;
+    execute!(stdout, LeaveAlternateScreen, Show)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+// Sends a request that the daemon answers in text, and returns the answer
+fn ask(request: &Request) -> io::Result<Option<String>> {
+    let Some(mut stream) = connect()? else { return Ok(None) };
+    send_request(&mut stream, request)?;
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    Ok(Some(answer))
+}
+
+// `partermai attach <name>`. A session open in this Partermai (`handover`) moves into the
+// daemon with its directories, history, variables and Voia conversation, so it survives
+// the terminal closing; returns whether it did.
+pub fn attach_session(name: &str, profile: Option<&str>, handover: Option<&Session>, env: &Environment, out: &mut impl Write) -> io::Result<bool> {
+    if let Ok(current) = env::var(SESSION_VAR) {
+        return Err(io::Error::other(format!("Already in daemon session {}; detach first", current)));
+    }
+    start()?;
+    let bundle = match handover {
+        Some(session) => {
+            let listing = ask(&Request::List)?.unwrap_or_default();
+            if listing.lines().any(|line| line.split('\t').next() == Some(name)) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                    format!("Session {} is open here and in the daemon; close one or pick another name", name)));
+            }
+            let path = handover_path(name)?;
+            crate::bundle::export(session, &path, &mut io::sink())?;
+            Some(path)
+        }
+        None => None,
+    };
+    let profile = profile.or(handover.and_then(|session| session.profile.as_deref()));
+    let result = attach(name, profile, bundle.clone(), env);
+    let moved = result.as_ref().is_ok_and(|attached| attached.shown) && bundle.is_some();
+    // The session reads the bundle when it starts; if it never did, it stays here
+    if let (Some(path), false) = (&bundle, moved) {
+        let _ = fs::remove_file(path);
+    }
+    let attached = result?;
+    writeln!(out, "{}", format!("[{}: {}]", name, attached.reason).dimmed())?;
+    if moved {
+        writeln!(out, "{} {} {}", "Moved session".green(), name.yellow(), "to the daemon; 'partermai attach' to it again from any terminal".green())?;
+    }
+    Ok(moved)
+}
+
+// `partermai daemon [stop]`, `partermai attach` and `partermai detach`
+pub fn execute_daemon_command(args: &[&str], out: &mut impl Write) -> io::Result<()> {
+    match args {
+        ["daemon"] => {
+            if start()? {
+                writeln!(out, "{}: {}", "Daemon started".green(), socket_path()?.display())
+            } else {
+                writeln!(out, "The daemon is already running")
+            }
+        }
+        ["daemon", "stop"] => match ask(&Request::Stop)? {
+            Some(_) => writeln!(out, "{}", "Daemon stopped".green()),
+            None => writeln!(out, "The daemon isn't running"),
+        },
+        ["attach"] => {
+            let listing = ask(&Request::List)?.unwrap_or_default();
+            if listing.is_empty() {
+                return writeln!(out, "No daemon sessions; start one with 'partermai attach <name>'");
+            }
+            writeln!(out, "Daemon sessions:")?;
+            for line in listing.lines() {
+                let (name, attached) = line.split_once('\t').unwrap_or((line, "0"));
+                writeln!(out, "  {} {}", name.yellow(), format!("({} attached)", attached).dimmed())?;
+            }
+            Ok(())
+        }
+        ["detach"] => {
+            let session = env::var(SESSION_VAR)
+                .map_err(|_| io::Error::other("Not in a daemon session; start one with 'partermai attach <name>'"))?;
+            ask(&Request::Detach { session })?;
+            Ok(())
+        }
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: partermai daemon [stop] | attach | detach")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_frame_waits_for_a_whole_frame() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, 7, b"hello").unwrap();
+        let mut partial = buffer[..6].to_vec();
+        assert!(take_frame(&mut partial).is_none());
+        assert_eq!(partial.len(), 6);
+        assert!(take_frame(&mut vec![7, 0]).is_none());
+    }
+
+    #[test]
+    fn take_frame_leaves_the_next_frame() {
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, 1, b"abc").unwrap();
+        write_frame(&mut buffer, 2, b"").unwrap();
+        assert_eq!(take_frame(&mut buffer), Some((1, b"abc".to_vec())));
+        assert_eq!(take_frame(&mut buffer), Some((2, Vec::new())));
+        assert!(buffer.is_empty());
+    }
+}
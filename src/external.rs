@@ -0,0 +1,204 @@
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+use colored::*;
+use crate::completion::BUILTINS;
+use crate::config::CorrectionConfig;
+use crate::history;
+use crate::which::find_executables;
+use crate::Environment;
+
+// execve's "not an executable format" error, the same number on Linux and macOS
+const ENOEXEC: i32 = 8;
+const MAX_SUGGESTIONS: usize = 3;
+
+// Edit distance counting a swap of neighbours as one edit, so `gerp` is one away from `grep`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1).min(rows[i][j - 1] + 1).min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+// Names of everything runnable: builtins, the session's aliases (unless `command` is
+// bypassing them) and the executables in each $PATH directory
+fn known_commands(env: &Environment, with_aliases: bool) -> BTreeSet<String> {
+    let mut names: BTreeSet<String> = BUILTINS.iter().map(|name| name.to_string()).collect();
+    if with_aliases {
+        names.extend(env.aliases.keys().cloned());
+    }
+    if let Some(paths) = env::var_os("PATH") {
+        for dir in env::split_paths(&paths) {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            names.extend(entries.filter_map(Result::ok).map(|entry| entry.file_name().to_string_lossy().to_string()));
+        }
+    }
+    names
+}
+
+// The closest known commands with their distances, nearest first; short names get less
+// slack so `l` doesn't suggest everything
+fn suggestions(cmd: &str, env: &Environment, with_aliases: bool) -> Vec<(usize, String)> {
+    let limit = if cmd.chars().count() <= 4 { 1 } else { 2 };
+    let mut candidates: Vec<(usize, String)> = known_commands(env, with_aliases)
+        .into_iter()
+        .map(|name| (edit_distance(cmd, &name), name))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    candidates.sort();
+    candidates.truncate(MAX_SUGGESTIONS);
+    candidates
+}
+
+// The interpreter named on a script's #! line
+fn interpreter(path: &Path) -> Option<String> {
+    let mut line = String::new();
+    BufReader::new(fs::File::open(path).ok()?).read_line(&mut line).ok()?;
+    line.strip_prefix("#!")?.split_whitespace().next().map(str::to_string)
+}
+
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "Y" | "yes")
+}
+
+// Explains why the program couldn't start. A missing program may be a typo, so this
+// suggests close matches and returns a corrected command line if the user wants it run.
+fn explain_spawn_error(prefix: &str, cmd: &str, args: &[&str], e: &io::Error, env: &Environment, config: &CorrectionConfig) -> Option<String> {
+    let found = find_executables(cmd, env).into_iter().next();
+    let message = match (e.kind(), &found) {
+        // The program is there, so the missing file must be its interpreter
+        (io::ErrorKind::NotFound, Some(path)) => match interpreter(path) {
+            Some(interpreter) => format!("bad interpreter: {}: no such file", interpreter),
+            None => e.to_string(),
+        },
+        (io::ErrorKind::NotFound, None) if cmd.contains('/') => "no such file or directory".to_string(),
+        (io::ErrorKind::NotFound, None) => "command not found".to_string(),
+        (io::ErrorKind::PermissionDenied, _) if env.resolve_path(cmd).is_dir() => "is a directory".to_string(),
+        (io::ErrorKind::PermissionDenied, _) => "permission denied".to_string(),
+        _ if e.raw_os_error() == Some(ENOEXEC) => "cannot execute: not a valid executable format".to_string(),
+        _ => e.to_string(),
+    };
+    println!("{}: {}", cmd.red(), message);
+
+    if e.kind() != io::ErrorKind::NotFound || found.is_some() || cmd.contains('/') {
+        return None;
+    }
+    let suggestions = suggestions(cmd, env, prefix.is_empty());
+    // Only offer to run a fix when it is strictly closer than every other candidate
+    let clear_fix = match suggestions.as_slice() {
+        [(best, name), rest @ ..] if rest.first().is_none_or(|(next, _)| next > best) => Some(name),
+        _ => None,
+    };
+    match clear_fix {
+        Some(name) if config.prompt && io::stdin().is_terminal() => {
+            let corrected = std::iter::once(name.to_string()).chain(args.iter().map(|arg| crate::args::quote(arg))).collect::<Vec<_>>().join(" ");
+            let corrected = format!("{}{}", prefix, corrected);
+            confirm(&format!("Did you mean {}?", corrected.bright_cyan())).then_some(corrected)
+        }
+        _ if suggestions.is_empty() => None,
+        _ => {
+            let list: Vec<String> = suggestions.iter().map(|(_, name)| name.bright_cyan().to_string()).collect();
+            println!("Did you mean: {}?", list.join(", "));
+            None
+        }
+    }
+}
+
+// A started program, on its own pty when Partermai is attached to a terminal
+enum Running {
+    Plain(Child),
+    #[cfg(unix)]
+    Pty(crate::pty::PtyChild),
+}
+
+impl Running {
+    fn wait(self) -> io::Result<ExitStatus> {
+        match self {
+            #[cfg(unix)]
+            Running::Plain(child) => crate::timing::wait_child(&child),
+            #[cfg(not(unix))]
+            Running::Plain(mut child) => child.wait(),
+            #[cfg(unix)]
+            Running::Pty(child) => child.wait(),
+        }
+    }
+}
+
+fn spawn(cmd: &str, args: &[&str], env: &Environment) -> io::Result<Running> {
+    crate::timing::note_program();
+    let mut command = Command::new(cmd);
+    command.args(args).current_dir(&env.current_dir).envs(&env.vars);
+    #[cfg(unix)]
+    if io::stdin().is_terminal() && io::stdout().is_terminal() {
+        return crate::pty::spawn(command).map(Running::Pty);
+    }
+    command.spawn().map(Running::Plain)
+}
+
+// Runs a program from $PATH in the session's directory and waits for it. When the
+// program can't be found and the user accepts a correction, that command line is returned,
+// starting with `prefix` (e.g. "command ") so it runs the same way as the original.
+pub fn run(prefix: &str, cmd: &str, args: &[&str], env: &Environment, config: &CorrectionConfig) -> Option<String> {
+    match spawn(cmd, args, env) {
+        Ok(running) => {
+            if !running.wait().is_ok_and(|status| status.success()) {
+                history::mark_failed();
+            }
+            None
+        }
+        Err(e) => {
+            history::mark_failed();
+            explain_spawn_error(prefix, cmd, args, &e, env, config)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("grep", "grep"), 0);
+        assert_eq!(edit_distance("gre", "grep"), 1);
+        assert_eq!(edit_distance("grap", "grep"), 1);
+        assert_eq!(edit_distance("", "ls"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn edit_distance_counts_a_swap_as_one_edit() {
+        assert_eq!(edit_distance("gerp", "grep"), 1);
+        assert_eq!(edit_distance("sl", "ls"), 1);
+    }
+
+    #[test]
+    fn suggestions_include_aliases_unless_bypassed() {
+        let mut env = Environment::new();
+        env.aliases.insert("zzdeploy".to_string(), "git push".to_string());
+        assert!(suggestions("zzdepoly", &env, true).iter().any(|(_, name)| name == "zzdeploy"));
+        assert!(suggestions("zzdepoly", &env, false).is_empty());
+    }
+}
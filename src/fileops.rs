@@ -0,0 +1,375 @@
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use colored::*;
+use crate::args::{parse_args, report, usage};
+use crate::trash;
+use crate::Environment;
+
+// Copies at least this big get a progress bar
+const PROGRESS_THRESHOLD: u64 = 64 * 1024 * 1024;
+const COPY_BUFFER_SIZE: usize = 256 * 1024;
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} {}", bytes, UNITS[0]) } else { format!("{:.1} {}", size, UNITS[unit]) }
+}
+
+// Progress bar for long copies, redrawn at most ten times a second
+struct Progress {
+    total: u64,
+    done: u64,
+    visible: bool,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    fn new(total: u64) -> Self {
+        Self {
+            total,
+            done: 0,
+            visible: total >= PROGRESS_THRESHOLD && io::stdout().is_terminal(),
+            last_draw: None,
+        }
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.done += bytes;
+        if !self.visible || self.last_draw.is_some_and(|t| t.elapsed() < Duration::from_millis(100)) {
+            return;
+        }
+        self.last_draw = Some(Instant::now());
+
+        let fraction = self.done as f64 / self.total.max(1) as f64;
+        let filled = ((fraction * PROGRESS_BAR_WIDTH as f64) as usize).min(PROGRESS_BAR_WIDTH);
+        print!("\r{} [{}{}] {:>3}% {} / {}  ",
+            "Copying".bright_cyan(),
+            "█".repeat(filled).green(),
+            "░".repeat(PROGRESS_BAR_WIDTH - filled),
+            (fraction * 100.0) as u32,
+            human_size(self.done),
+            human_size(self.total));
+        let _ = io::stdout().flush();
+    }
+
+    fn finish(&self) {
+        if self.visible && self.last_draw.is_some() {
+            // Clear the bar so the prompt starts on a clean line
+            print!("\r\x1B[2K");
+            let _ = io::stdout().flush();
+        }
+    }
+}
+
+// Enough information to reverse a destructive file operation with `undo`
+#[derive(Default)]
+pub struct FileOperation {
+    pub command: String,             // What the user ran, for the undo message
+    created: Vec<PathBuf>,           // Moved to the trash on undo
+    moved: Vec<(PathBuf, PathBuf)>,  // (from, to) pairs, moved back on undo
+    trashed: Vec<String>,            // Names in the trash, restored on undo
+}
+
+impl FileOperation {
+    fn into_option(self) -> Option<Self> {
+        if self.created.is_empty() && self.moved.is_empty() && self.trashed.is_empty() {
+            None
+        } else {
+            Some(self)
+        }
+    }
+
+    // Reverses the operation step by step, reporting anything that can't be put back
+    pub fn undo(&self) -> io::Result<()> {
+        let mut failures = 0;
+        for path in self.created.iter().rev() {
+            if let Err(e) = trash::move_to_trash(path) {
+                report("undo", &path.display().to_string(), &e);
+                failures += 1;
+            }
+        }
+        for (from, to) in self.moved.iter().rev() {
+            if let Err(e) = move_path(to, from) {
+                report("undo", &to.display().to_string(), &e);
+                failures += 1;
+            }
+        }
+        for name in self.trashed.iter().rev() {
+            if let Err(e) = trash::restore(name) {
+                report("undo", name, &e);
+                failures += 1;
+            }
+        }
+        if failures > 0 {
+            return Err(io::Error::other(format!("{} step(s) could not be undone", failures)));
+        }
+        Ok(())
+    }
+}
+
+// Anything about to be overwritten goes to the trash first so `undo` can bring it back
+fn trash_existing(path: &Path, operation: &mut FileOperation) -> io::Result<()> {
+    if path.symlink_metadata().is_ok() {
+        operation.trashed.push(trash::move_to_trash(path)?);
+    }
+    Ok(())
+}
+
+pub fn execute_mkdir(args: &[&str], env: &Environment) -> io::Result<()> {
+    let parsed = parse_args("mkdir", args, "p", "")?;
+    let operands = &parsed.operands;
+    if operands.is_empty() {
+        return Err(usage("mkdir [-p] <dir>..."));
+    }
+    for operand in operands {
+        let path = env.resolve_path(operand);
+        let result = if parsed.has('p') { fs::create_dir_all(&path) } else { fs::create_dir(&path) };
+        if let Err(e) = result {
+            report("mkdir", operand, &e);
+        }
+    }
+    Ok(())
+}
+
+pub fn execute_touch(args: &[&str], env: &Environment) -> io::Result<()> {
+    let operands = parse_args("touch", args, "", "")?.operands;
+    if operands.is_empty() {
+        return Err(usage("touch <file>..."));
+    }
+    for operand in operands {
+        let path = env.resolve_path(operand);
+        let result = File::options()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(e) = result {
+            report("touch", operand, &e);
+        }
+    }
+    Ok(())
+}
+
+// Total size of the regular files below `path`, for the progress bar
+fn total_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else { return 0 };
+    if metadata.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| entries.filter_map(Result::ok).map(|entry| total_size(&entry.path())).sum())
+            .unwrap_or(0)
+    } else if metadata.is_file() {
+        metadata.len()
+    } else {
+        0
+    }
+}
+
+fn copy_file(from: &Path, to: &Path, progress: &mut Progress) -> io::Result<()> {
+    let mut source = File::open(from)?;
+    let mut dest = File::create(to)?;
+    let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+    loop {
+        let n = source.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        dest.write_all(&buf[..n])?;
+        progress.advance(n as u64);
+    }
+    fs::set_permissions(to, fs::metadata(from)?.permissions())
+}
+
+// Copies files, directories (recursively) and symlinks (as links)
+fn copy_path(from: &Path, to: &Path, progress: &mut Progress) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(from)?;
+    if metadata.is_symlink() {
+        let target = fs::read_link(from)?;
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, to);
+        #[cfg(not(unix))]
+        return copy_file(&from.parent().unwrap_or(Path::new(".")).join(target), to, progress);
+    }
+    if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_path(&entry.path(), &to.join(entry.file_name()), progress)?;
+        }
+        return fs::set_permissions(to, metadata.permissions());
+    }
+    copy_file(from, to, progress)
+}
+
+// Where a source ends up: inside `dest` if it is a directory, otherwise `dest` itself
+fn destination_for(source: &Path, dest: &Path) -> io::Result<PathBuf> {
+    if dest.is_dir() {
+        let name = source.file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid source name"))?;
+        Ok(dest.join(name))
+    } else {
+        Ok(dest.to_path_buf())
+    }
+}
+
+// Copying or moving a file onto itself would trash the source before reading it
+fn check_not_same_file(source: &str, from: &Path, to: &Path) -> io::Result<()> {
+    match (fs::canonicalize(from), fs::canonicalize(to)) {
+        (Ok(a), Ok(b)) if a == b => Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("'{}' and '{}' are the same file", source, to.display()))),
+        _ => Ok(()),
+    }
+}
+
+// Sources plus a destination, which must be a directory when there are several sources
+fn sources_and_dest<'a>(operands: &[&'a str], env: &Environment, usage_text: &str) -> io::Result<(Vec<&'a str>, PathBuf)> {
+    let Some((dest, sources)) = operands.split_last() else { return Err(usage(usage_text)) };
+    if sources.is_empty() {
+        return Err(usage(usage_text));
+    }
+    let dest = env.resolve_path(dest);
+    if sources.len() > 1 && !dest.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("target '{}' is not a directory", dest.display())));
+    }
+    Ok((sources.to_vec(), dest))
+}
+
+pub fn execute_cp(args: &[&str], env: &Environment) -> io::Result<Option<FileOperation>> {
+    let parsed = parse_args("cp", args, "rR", "")?;
+    let recursive = parsed.has('r') || parsed.has('R');
+    let (sources, dest) = sources_and_dest(&parsed.operands, env, "cp [-r] <source>... <dest>")?;
+
+    let total = sources.iter().map(|source| total_size(&env.resolve_path(source))).sum();
+    let mut progress = Progress::new(total);
+    let mut operation = FileOperation::default();
+
+    for source in sources {
+        let from = env.resolve_path(source);
+        let result = destination_for(&from, &dest).and_then(|to| {
+            if from.is_dir() && !from.is_symlink() {
+                if !recursive {
+                    return Err(io::Error::other("is a directory (use -r)"));
+                }
+                if to.starts_with(&from) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot copy a directory into itself"));
+                }
+            }
+            check_not_same_file(source, &from, &to)?;
+            trash_existing(&to, &mut operation)?;
+            let copied = copy_path(&from, &to, &mut progress);
+            // Record partial copies too, so undo can clean them up
+            if to.symlink_metadata().is_ok() {
+                operation.created.push(to);
+            }
+            copied
+        });
+        if let Err(e) = result {
+            progress.finish();
+            report("cp", source, &e);
+        }
+    }
+    progress.finish();
+    Ok(operation.into_option())
+}
+
+// Renames, falling back to copy and delete when the rename would cross filesystems
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut progress = Progress::new(total_size(from));
+            let copied = copy_path(from, to, &mut progress);
+            progress.finish();
+            copied?;
+            remove_path(from, true)
+        }
+        other => other,
+    }
+}
+
+pub fn execute_mv(args: &[&str], env: &Environment) -> io::Result<Option<FileOperation>> {
+    let operands = parse_args("mv", args, "", "")?.operands;
+    let (sources, dest) = sources_and_dest(&operands, env, "mv <source>... <dest>")?;
+    let mut operation = FileOperation::default();
+
+    for source in sources {
+        let from = env.resolve_path(source);
+        let result = destination_for(&from, &dest).and_then(|to| {
+            if to.starts_with(&from) && from.is_dir() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot move a directory into itself"));
+            }
+            from.symlink_metadata()?;
+            check_not_same_file(source, &from, &to)?;
+            trash_existing(&to, &mut operation)?;
+            move_path(&from, &to)?;
+            operation.moved.push((from, to));
+            Ok(())
+        });
+        if let Err(e) = result {
+            report("mv", source, &e);
+        }
+    }
+    Ok(operation.into_option())
+}
+
+pub fn remove_path(path: &Path, recursive: bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        if !recursive {
+            return Err(io::Error::other("is a directory (use -r)"));
+        }
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+// Moves files to the trash, or deletes them for good with --permanent
+pub fn execute_rm(args: &[&str], env: &Environment) -> io::Result<Option<FileOperation>> {
+    let permanent = args.contains(&"--permanent");
+    let args: Vec<&str> = args.iter().copied().filter(|arg| *arg != "--permanent").collect();
+    let parsed = parse_args("rm", &args, "rRf", "")?;
+    let operands = &parsed.operands;
+    let recursive = parsed.has('r') || parsed.has('R');
+    let force = parsed.has('f');
+    if operands.is_empty() {
+        return Err(usage("rm [-r] [-f] [--permanent] <path>..."));
+    }
+    let mut operation = FileOperation::default();
+
+    for operand in operands {
+        let path = env.resolve_path(operand);
+        if path.parent().is_none() {
+            report("rm", operand, &io::Error::new(io::ErrorKind::PermissionDenied, "refusing to remove '/'"));
+            continue;
+        }
+        if matches!(operand.trim_end_matches('/').rsplit('/').next(), Some("." | "..")) {
+            report("rm", operand, &io::Error::new(io::ErrorKind::PermissionDenied, "refusing to remove '.' or '..'"));
+            continue;
+        }
+        let result = if permanent {
+            remove_path(&path, recursive)
+        } else {
+            fs::symlink_metadata(&path).and_then(|metadata| {
+                if metadata.is_dir() && !recursive {
+                    return Err(io::Error::other("is a directory (use -r)"));
+                }
+                operation.trashed.push(trash::move_to_trash(&path)?);
+                Ok(())
+            })
+        };
+        match result {
+            Err(e) if force && e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => report("rm", operand, &e),
+            Ok(()) => {}
+        }
+    }
+    Ok(operation.into_option())
+}
@@ -0,0 +1,254 @@
+use std::fs::Metadata;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::SystemTime;
+use colored::*;
+use globset::{GlobBuilder, GlobMatcher};
+use ignore::WalkBuilder;
+use regex::Regex;
+use crate::ls::{FileKind, LsColors};
+use crate::Environment;
+
+const USAGE: &str = "find [path]... [-name glob] [-iname glob] [-regex re] [-type f|d|l] \
+    [-size [+|-]N[k|M|G]] [-mtime [+|-]N] [-maxdepth N] [-hidden] [-no-ignore]";
+
+#[derive(Clone, Copy, PartialEq)]
+enum EntryType {
+    File,
+    Directory,
+    Symlink,
+}
+
+// `+N` means more than N, `-N` less than N and a bare `N` exactly N
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    MoreThan(u64),
+    LessThan(u64),
+    Exactly(u64),
+}
+
+impl Comparison {
+    fn parse(value: &str) -> Option<(Self, &str)> {
+        let (make, rest): (fn(u64) -> Self, &str) = match value.as_bytes().first() {
+            Some(b'+') => (Comparison::MoreThan, &value[1..]),
+            Some(b'-') => (Comparison::LessThan, &value[1..]),
+            _ => (Comparison::Exactly, value),
+        };
+        let end = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let n = rest[..end].parse().ok()?;
+        Some((make(n), &rest[end..]))
+    }
+
+    fn matches(self, value: u64) -> bool {
+        match self {
+            Comparison::MoreThan(n) => value > n,
+            Comparison::LessThan(n) => value < n,
+            Comparison::Exactly(n) => value == n,
+        }
+    }
+}
+
+#[derive(Default)]
+struct FindOptions {
+    name: Option<GlobMatcher>,
+    regex: Option<Regex>,               // Matched against the whole printed path
+    entry_type: Option<EntryType>,
+    size: Option<(Comparison, u64)>,    // Comparison in units of the given size
+    mtime: Option<Comparison>,          // Days since last modification
+    max_depth: Option<usize>,
+    hidden: bool,
+    no_ignore: bool,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn glob(pattern: &str, case_insensitive: bool) -> io::Result<GlobMatcher> {
+    GlobBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()
+        .map(|glob| glob.compile_matcher())
+        .map_err(|e| invalid(format!("find: {}", e)))
+}
+
+fn parse_size(value: &str) -> io::Result<(Comparison, u64)> {
+    let parsed = Comparison::parse(value).and_then(|(comparison, suffix)| {
+        let unit = match suffix {
+            "" | "c" => 1,
+            "k" | "K" => 1024,
+            "M" => 1024 * 1024,
+            "G" => 1024 * 1024 * 1024,
+            _ => return None,
+        };
+        Some((comparison, unit))
+    });
+    parsed.ok_or_else(|| invalid(format!("find: invalid size '{}'", value)))
+}
+
+fn parse_options<'a>(args: &[&'a str]) -> io::Result<(FindOptions, Vec<&'a str>)> {
+    let mut options = FindOptions::default();
+    let mut paths = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match *arg {
+            "-hidden" => options.hidden = true,
+            "-no-ignore" => options.no_ignore = true,
+            "-name" | "-iname" | "-regex" | "-type" | "-size" | "-mtime" | "-maxdepth" => {
+                let value = iter.next().ok_or_else(|| invalid(format!("find: {} requires an argument", arg)))?;
+                match *arg {
+                    "-name" => options.name = Some(glob(value, false)?),
+                    "-iname" => options.name = Some(glob(value, true)?),
+                    "-regex" => options.regex = Some(Regex::new(value).map_err(|e| invalid(format!("find: {}", e)))?),
+                    "-type" => options.entry_type = Some(match *value {
+                        "f" => EntryType::File,
+                        "d" => EntryType::Directory,
+                        "l" => EntryType::Symlink,
+                        _ => return Err(invalid(format!("find: unknown type '{}', use f, d or l", value))),
+                    }),
+                    "-size" => options.size = Some(parse_size(value)?),
+                    "-mtime" => options.mtime = Some(Comparison::parse(value)
+                        .filter(|(_, rest)| rest.is_empty())
+                        .map(|(comparison, _)| comparison)
+                        .ok_or_else(|| invalid(format!("find: invalid day count '{}'", value)))?),
+                    _ => options.max_depth = Some(value.parse()
+                        .map_err(|_| invalid(format!("find: invalid depth '{}'", value)))?),
+                }
+            }
+            other if other.starts_with('-') && other.len() > 1 => {
+                return Err(invalid(format!("find: unknown option '{}'\nUsage: {}", other, USAGE)));
+            }
+            path => paths.push(path),
+        }
+    }
+    Ok((options, paths))
+}
+
+fn entry_type(metadata: &Metadata) -> Option<EntryType> {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        Some(EntryType::Symlink)
+    } else if file_type.is_dir() {
+        Some(EntryType::Directory)
+    } else if file_type.is_file() {
+        Some(EntryType::File)
+    } else {
+        None
+    }
+}
+
+fn age_in_days(metadata: &Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    let age = SystemTime::now().duration_since(modified).unwrap_or_default();
+    Some(age.as_secs() / 86_400)
+}
+
+// Checks every filter given; the walker has already applied depth, hidden and ignore rules
+fn matches(options: &FindOptions, name: &str, shown: &str, metadata: &Metadata) -> bool {
+    if options.name.as_ref().is_some_and(|glob| !glob.is_match(name)) {
+        return false;
+    }
+    if options.regex.as_ref().is_some_and(|regex| !regex.is_match(shown)) {
+        return false;
+    }
+    if options.entry_type.is_some_and(|wanted| entry_type(metadata) != Some(wanted)) {
+        return false;
+    }
+    if let Some((comparison, unit)) = options.size {
+        // Sizes round up to whole units, so `-size -1M` means empty, as in GNU find
+        if !comparison.matches(metadata.len().div_ceil(unit)) {
+            return false;
+        }
+    }
+    if let Some(comparison) = options.mtime {
+        if !age_in_days(metadata).is_some_and(|days| comparison.matches(days)) {
+            return false;
+        }
+    }
+    true
+}
+
+pub fn execute_find(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let (options, paths) = parse_options(args)?;
+    let paths = if paths.is_empty() { vec!["."] } else { paths };
+    let colors = LsColors::from_env();
+
+    for operand in paths {
+        let root = env.resolve_path(operand);
+        if root.symlink_metadata().is_err() {
+            println!("{}: find: {}: No such file or directory", "Error".red(), operand);
+            crate::history::mark_failed();
+            continue;
+        }
+
+        let walker = WalkBuilder::new(&root)
+            .hidden(!options.hidden)
+            .git_ignore(!options.no_ignore)
+            .git_global(!options.no_ignore)
+            .git_exclude(!options.no_ignore)
+            .ignore(!options.no_ignore)
+            .max_depth(options.max_depth)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        for entry in walker.filter_map(Result::ok) {
+            let path = entry.path();
+            // Printed paths start with the operand as typed, like GNU find
+            let shown = match path.strip_prefix(&root) {
+                Ok(relative) if relative.as_os_str().is_empty() => operand.to_string(),
+                Ok(relative) => Path::new(operand).join(relative).display().to_string(),
+                Err(_) => path.display().to_string(),
+            };
+            let name = path.file_name().map_or_else(|| shown.clone(), |name| name.to_string_lossy().to_string());
+            let Ok(metadata) = path.symlink_metadata() else { continue };
+
+            if matches(&options, &name, &shown, &metadata) {
+                writeln!(out, "{}", colors.paint(&shown, FileKind::of(path)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comparison_reads_sign_and_number() {
+        assert_eq!(Comparison::parse("+5"), Some((Comparison::MoreThan(5), "")));
+        assert_eq!(Comparison::parse("-5"), Some((Comparison::LessThan(5), "")));
+        assert_eq!(Comparison::parse("5k"), Some((Comparison::Exactly(5), "k")));
+        assert_eq!(Comparison::parse("+"), None);
+        assert_eq!(Comparison::parse("abc"), None);
+        assert_eq!(Comparison::parse(""), None);
+    }
+
+    #[test]
+    fn comparison_matches() {
+        assert!(Comparison::MoreThan(5).matches(6));
+        assert!(!Comparison::MoreThan(5).matches(5));
+        assert!(Comparison::LessThan(5).matches(4));
+        assert!(!Comparison::LessThan(5).matches(5));
+        assert!(Comparison::Exactly(5).matches(5));
+    }
+
+    #[test]
+    fn parse_size_reads_suffixes() {
+        assert_eq!(parse_size("100").unwrap(), (Comparison::Exactly(100), 1));
+        assert_eq!(parse_size("+100c").unwrap(), (Comparison::MoreThan(100), 1));
+        assert_eq!(parse_size("-2k").unwrap(), (Comparison::LessThan(2), 1024));
+        assert_eq!(parse_size("2K").unwrap(), (Comparison::Exactly(2), 1024));
+        assert_eq!(parse_size("+1M").unwrap(), (Comparison::MoreThan(1), 1024 * 1024));
+        assert_eq!(parse_size("3G").unwrap(), (Comparison::Exactly(3), 1024 * 1024 * 1024));
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_input() {
+        for value in ["", "+", "k", "10m", "10KB", "1.5M", "ten"] {
+            let e = parse_size(value).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+            assert!(e.to_string().contains(value));
+        }
+    }
+}
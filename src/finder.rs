@@ -0,0 +1,74 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use ignore::WalkBuilder;
+use rustyline::{Cmd, ConditionalEventHandler, EventContext, RepeatCount};
+use crate::picker::{self, PickerSource};
+
+// Stop walking huge trees; nobody scrolls past this many candidates
+const MAX_CANDIDATES: usize = 100_000;
+
+// Files and directories below `dir`, relative to it, honoring .gitignore like `tree`
+fn collect_candidates(dir: &Path) -> Vec<String> {
+    WalkBuilder::new(dir)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.depth() > 0)
+        .take(MAX_CANDIDATES)
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(dir).ok()?.display().to_string();
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            Some(if is_dir { format!("{}/", relative) } else { relative })
+        })
+        .collect()
+}
+
+struct Files {
+    paths: Vec<String>,
+}
+
+impl PickerSource for Files {
+    fn text(&self, index: usize) -> &str {
+        &self.paths[index]
+    }
+
+    fn filtered(&self) -> Vec<usize> {
+        (0..self.paths.len()).collect()
+    }
+
+    fn status(&self, matches: usize) -> String {
+        format!("  {}/{}", matches, self.paths.len())
+    }
+}
+
+// Lets the user pick a file below `dir`; None if they cancel or nothing is there
+pub fn pick(dir: &Path) -> io::Result<Option<String>> {
+    let mut paths = collect_candidates(dir);
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    // Equally good matches then go to shorter paths, which are usually what you want
+    paths.sort_by_key(String::len);
+    picker::pick(Files { paths }, "")
+}
+
+// Ctrl+T in the line editor: pick a file and insert its path at the cursor. The run
+// loop keeps `dir` pointing at the session's current directory.
+pub struct FinderKey {
+    pub dir: Arc<Mutex<PathBuf>>,
+}
+
+impl ConditionalEventHandler for FinderKey {
+    fn handle(&self, _: &rustyline::Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let dir = self.dir.lock().ok()?.clone();
+        match pick(&dir) {
+            Ok(Some(path)) => {
+                // Keep the path separate from whatever is already typed before the cursor
+                let needs_space = ctx.line()[..ctx.pos()].chars().last().is_some_and(|c| !c.is_whitespace());
+                let path = crate::args::quote(&path);
+                Some(Cmd::Insert(1, if needs_space { format!(" {}", path) } else { path }))
+            }
+            _ => Some(Cmd::Noop),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::*;
+use serde::{Deserialize, Serialize};
+use crate::Environment;
+
+// Once the scores add up to more than this, everything is aged so old entries fade out
+const MAX_TOTAL_SCORE: f64 = 10_000.0;
+// Matches scoring within this ratio of the best one are treated as ambiguous
+const AMBIGUITY_RATIO: f64 = 0.9;
+const MAX_CHOICES: usize = 9;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct DirEntry {
+    path: PathBuf,
+    score: f64,
+    last_access: u64, // Seconds since the Unix epoch
+}
+
+impl DirEntry {
+    // Visit count weighted by how recently the directory was used, like z and zoxide
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_access);
+        let weight = if age < 3600 {
+            4.0
+        } else if age < 86_400 {
+            2.0
+        } else if age < 604_800 {
+            0.5
+        } else {
+            0.25
+        };
+        self.score * weight
+    }
+}
+
+// Directory database behind `z`, stored in the Partermai data directory
+#[derive(Serialize, Deserialize, Default)]
+struct FrecencyDb {
+    entries: Vec<DirEntry>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl FrecencyDb {
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("partermai").join("frecency.json"))
+    }
+
+    fn load() -> Self {
+        Self::path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory"))?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(self)?)
+    }
+
+    fn record(&mut self, dir: &Path) {
+        let now = now();
+        match self.entries.iter_mut().find(|entry| entry.path == dir) {
+            Some(entry) => {
+                entry.score += 1.0;
+                entry.last_access = now;
+            }
+            None => self.entries.push(DirEntry { path: dir.to_path_buf(), score: 1.0, last_access: now }),
+        }
+
+        let total: f64 = self.entries.iter().map(|entry| entry.score).sum();
+        if total > MAX_TOTAL_SCORE {
+            for entry in &mut self.entries {
+                entry.score *= 0.9;
+            }
+            self.entries.retain(|entry| entry.score >= 1.0);
+        }
+    }
+
+    // Existing directories matching all keywords, best first
+    fn query(&self, keywords: &[&str], exclude: &Path) -> Vec<DirEntry> {
+        let now = now();
+        let mut matches: Vec<DirEntry> = self.entries.iter()
+            .filter(|entry| entry.path != exclude && matches_keywords(&entry.path, keywords))
+            .filter(|entry| entry.path.is_dir())
+            .cloned()
+            .collect();
+        matches.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        matches
+    }
+}
+
+// Keywords must appear in order, and the last one must be in the final path component
+fn matches_keywords(path: &Path, keywords: &[&str]) -> bool {
+    let haystack = path.to_string_lossy().to_lowercase();
+    let mut rest = haystack.as_str();
+    for keyword in keywords {
+        let keyword = keyword.to_lowercase();
+        match rest.find(&keyword) {
+            Some(i) => rest = &rest[i + keyword.len()..],
+            None => return false,
+        }
+    }
+
+    match (keywords.last(), path.file_name()) {
+        (Some(last), Some(name)) => name.to_string_lossy().to_lowercase().contains(&last.to_lowercase()),
+        (None, _) => true,
+        (Some(_), None) => false,
+    }
+}
+
+// Records a visit; failures to read or write the database are not worth interrupting `cd` for
+pub fn record_visit(dir: &Path) {
+    let mut db = FrecencyDb::load();
+    db.record(dir);
+    let _ = db.save();
+}
+
+// Picks the directory to jump to, asking the user when the top matches score about the same
+fn choose(matches: &[DirEntry], interactive: bool) -> io::Result<Option<PathBuf>> {
+    let Some(best) = matches.first() else { return Ok(None) };
+    let now = now();
+    let ambiguous = matches.get(1)
+        .is_some_and(|second| second.frecency(now) >= best.frecency(now) * AMBIGUITY_RATIO);
+    if !interactive && !ambiguous {
+        return Ok(Some(best.path.clone()));
+    }
+
+    let choices = &matches[..matches.len().min(MAX_CHOICES)];
+    for (i, entry) in choices.iter().enumerate() {
+        println!("  {} {:>6.1}  {}", (i + 1).to_string().bright_cyan(), entry.frecency(now), entry.path.display());
+    }
+    print!("Select [1-{}] (Enter for 1): ", choices.len());
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer.is_empty() {
+        return Ok(Some(best.path.clone()));
+    }
+    match answer.parse::<usize>() {
+        Ok(n) if (1..=choices.len()).contains(&n) => Ok(Some(choices[n - 1].path.clone())),
+        _ => Ok(None),
+    }
+}
+
+pub fn execute_z(args: &[&str], env: &mut Environment) -> io::Result<()> {
+    let mut interactive = false;
+    let mut list = false;
+    let mut keywords = Vec::new();
+    for arg in args {
+        match *arg {
+            "-i" => interactive = true,
+            "-l" => list = true,
+            _ => keywords.push(*arg),
+        }
+    }
+
+    if list {
+        let now = now();
+        let mut entries = FrecencyDb::load().entries;
+        entries.sort_by(|a, b| b.frecency(now).total_cmp(&a.frecency(now)));
+        for entry in entries {
+            println!("{:>8.1}  {}", entry.frecency(now), env.tilde_path(&entry.path));
+        }
+        return Ok(());
+    }
+
+    // Plain paths and the usual cd shorthands work too
+    match keywords.as_slice() {
+        [] => return env.change_directory("~", false),
+        ["-"] => return env.change_directory("-", false),
+        [path] if env.resolve_path(path).is_dir() => return env.change_directory(path, false),
+        _ => {}
+    }
+
+    let matches = FrecencyDb::load().query(&keywords, &env.current_dir);
+    match choose(&matches, interactive)? {
+        Some(dir) => env.set_directory(dir),
+        None if matches.is_empty() => Err(io::Error::new(io::ErrorKind::NotFound, "No matching directory")),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, score: f64, last_access: u64) -> DirEntry {
+        DirEntry { path: PathBuf::from(path), score, last_access }
+    }
+
+    #[test]
+    fn keywords_match_in_order() {
+        let path = Path::new("/home/me/Projects/partermai/src");
+        assert!(matches_keywords(path, &["proj", "src"]));
+        assert!(matches_keywords(path, &["PART", "SRC"]));
+        assert!(!matches_keywords(path, &["src", "proj"]));
+        assert!(matches_keywords(path, &[]));
+    }
+
+    #[test]
+    fn last_keyword_must_be_in_the_final_component() {
+        assert!(!matches_keywords(Path::new("/home/me/partermai/src"), &["partermai"]));
+        assert!(matches_keywords(Path::new("/home/me/partermai/src"), &["partermai", "sr"]));
+    }
+
+    #[test]
+    fn recent_visits_weigh_more() {
+        let now = 1_000_000_000;
+        assert_eq!(entry("/a", 2.0, now - 10).frecency(now), 8.0);
+        assert_eq!(entry("/a", 2.0, now - 7_200).frecency(now), 4.0);
+        assert_eq!(entry("/a", 2.0, now - 172_800).frecency(now), 1.0);
+        assert_eq!(entry("/a", 2.0, now - 1_000_000).frecency(now), 0.5);
+    }
+
+    #[test]
+    fn record_counts_visits_and_ages_old_entries() {
+        let mut db = FrecencyDb::default();
+        db.record(Path::new("/a"));
+        db.record(Path::new("/a"));
+        db.record(Path::new("/b"));
+        assert_eq!(db.entries.len(), 2);
+        assert_eq!(db.entries[0].score, 2.0);
+
+        // Going over the total scales every score down and drops what falls below one visit
+        db.entries.push(entry("/big", MAX_TOTAL_SCORE, 0));
+        db.entries[1].score = 1.05;
+        db.record(Path::new("/a"));
+        assert_eq!(db.entries.iter().map(|e| e.path.to_str().unwrap()).collect::<Vec<_>>(), vec!["/a", "/big"]);
+        assert!((db.entries[0].score - 2.7).abs() < 1e-9);
+        assert_eq!(db.entries[1].score, MAX_TOTAL_SCORE * 0.9);
+    }
+
+    #[test]
+    fn query_ranks_existing_matches_by_frecency() {
+        let root = std::env::temp_dir().join(format!("partermai-frecency-{}", std::process::id()));
+        for name in ["old-src", "new-src", "other"] {
+            fs::create_dir_all(root.join(name)).unwrap();
+        }
+        let path = |name: &str| root.join(name).to_string_lossy().to_string();
+        let now = now();
+        let db = FrecencyDb { entries: vec![
+            entry(&path("old-src"), 10.0, now - 1_000_000), // 2.5
+            entry(&path("new-src"), 1.0, now),              // 4.0
+            entry(&path("gone-src"), 50.0, now),            // Not on disk any more
+            entry(&path("other"), 50.0, now),
+        ] };
+
+        let found: Vec<PathBuf> = db.query(&["src"], Path::new("/")).into_iter().map(|e| e.path).collect();
+        assert_eq!(found, vec![root.join("new-src"), root.join("old-src")]);
+        let found = db.query(&["src"], &root.join("new-src"));
+        assert_eq!(found.len(), 1);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use colored::*;
+
+// Status of a single path, as the two porcelain columns (index, worktree)
+#[derive(Clone, Copy, PartialEq)]
+pub struct GitStatus {
+    pub staged: char,
+    pub worktree: char,
+}
+
+impl GitStatus {
+    const CLEAN: GitStatus = GitStatus { staged: ' ', worktree: ' ' };
+
+    pub fn is_ignored(&self) -> bool {
+        self.staged == '!'
+    }
+
+    fn is_untracked(&self) -> bool {
+        self.staged == '?'
+    }
+
+    // Two-character marker shown in front of `ls` entries
+    pub fn marker(&self) -> String {
+        if self.is_ignored() {
+            return "!!".dimmed().to_string();
+        }
+        if self.is_untracked() {
+            return "??".red().to_string();
+        }
+        let staged = match self.staged {
+            ' ' => "-".dimmed(),
+            c => c.to_string().green(),
+        };
+        let worktree = match self.worktree {
+            ' ' => "-".dimmed(),
+            c => c.to_string().yellow(),
+        };
+        format!("{}{}", staged, worktree)
+    }
+}
+
+// Working tree status of one directory of a repository, taken from `git status`
+pub struct GitRepo {
+    root: PathBuf,
+    entries: Vec<(PathBuf, GitStatus)>,
+}
+
+impl GitRepo {
+    // Returns None when `dir` is not inside a git repository or git is unavailable. Only
+    // `dir` is scanned, so listing a small directory of a big repository stays cheap.
+    pub fn discover(dir: &Path) -> Option<Self> {
+        let output = Command::new("git")
+            .args(["rev-parse", "--show-toplevel"])
+            .current_dir(dir)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let root = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+        let scope = dir.canonicalize().ok()?.strip_prefix(&root).ok()?.to_path_buf();
+        let scope = if scope.as_os_str().is_empty() { PathBuf::from(".") } else { scope };
+
+        // git still reports an untracked or ignored directory the scope is inside of
+        let output = Command::new("git")
+            .args(["--literal-pathspecs", "--no-optional-locks", "status", "--porcelain=v1", "-z",
+                "--ignored=matching", "--untracked-files=normal", "--"])
+            .arg(&scope)
+            .current_dir(&root)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut records = stdout.split('\0').filter(|r| !r.is_empty());
+        let mut entries = Vec::new();
+        while let Some(record) = records.next() {
+            let mut chars = record.chars();
+            let (Some(staged), Some(worktree)) = (chars.next(), chars.next()) else { continue };
+            let path = record.get(3..).unwrap_or_default().trim_end_matches('/');
+            entries.push((PathBuf::from(path), GitStatus { staged, worktree }));
+            // Renames and copies are followed by the original path
+            if matches!(staged, 'R' | 'C') {
+                records.next();
+            }
+        }
+
+        Some(Self { root, entries })
+    }
+
+    // Path relative to the repository root; symlinks themselves are not followed
+    fn relative(&self, path: &Path) -> Option<PathBuf> {
+        let absolute = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => parent.canonicalize().ok()?.join(name),
+            _ => path.canonicalize().ok()?,
+        };
+        absolute.strip_prefix(&self.root).ok().map(Path::to_path_buf)
+    }
+
+    // Status of a file, or the combined status of everything below a directory
+    pub fn status_of(&self, path: &Path) -> GitStatus {
+        let Some(relative) = self.relative(path) else {
+            return GitStatus::CLEAN;
+        };
+
+        let mut combined = GitStatus::CLEAN;
+        for (entry, status) in &self.entries {
+            // Exact hits cover files as well as wholly untracked or ignored directories
+            if *entry == relative {
+                return *status;
+            }
+            // git lists only the top of an untracked or ignored directory, not what is in it
+            if (status.is_untracked() || status.is_ignored()) && relative.starts_with(entry) {
+                return *status;
+            }
+            if !entry.starts_with(&relative) || status.is_ignored() {
+                continue;
+            }
+            if status.is_untracked() {
+                if combined.worktree == ' ' {
+                    combined.worktree = '?';
+                }
+                continue;
+            }
+            if combined.staged == ' ' {
+                combined.staged = status.staged;
+            }
+            if matches!(combined.worktree, ' ' | '?') && status.worktree != ' ' {
+                combined.worktree = status.worktree;
+            }
+        }
+        combined
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use colored::*;
+use crossterm::event::KeyCode;
+use rustyline::{Cmd, ConditionalEventHandler, EventContext, Movement, RepeatCount};
+use serde::{Deserialize, Serialize};
+use crate::picker::{self, KeyAction, PickerSource};
+
+// Only the most recent entries are offered by the picker
+const MAX_LOADED: usize = 10_000;
+
+// Set whenever an error is reported, so the history knows how the command went
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+pub fn mark_failed() {
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+#[derive(Serialize, Deserialize)]
+struct HistoryEntry {
+    command: String,
+    session: String,
+    cwd: PathBuf,
+    time: u64, // Seconds since the Unix epoch
+    success: bool,
+}
+
+// One JSON object per line, so recording a command is a cheap append
+fn path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("partermai").join("history.jsonl"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Records a finished command along with whether any error was reported while it ran
+pub fn record(command: &str, session: &str, cwd: &Path) {
+    let entry = HistoryEntry {
+        command: command.to_string(),
+        session: session.to_string(),
+        cwd: cwd.to_path_buf(),
+        time: now(),
+        success: !FAILED.swap(false, Ordering::Relaxed),
+    };
+    // Losing a history entry isn't worth interrupting the user for
+    let _ = append(&entry);
+}
+
+fn append(entry: &HistoryEntry) -> io::Result<()> {
+    let path = path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
+
+// Newest first
+fn load() -> Vec<HistoryEntry> {
+    let Some(content) = path().and_then(|path| fs::read_to_string(path).ok()) else { return Vec::new() };
+    content.lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .take(MAX_LOADED)
+        .collect()
+}
+
+fn ago(time: u64) -> String {
+    let secs = now().saturating_sub(time);
+    match secs {
+        0..=59 => format!("{}s ago", secs),
+        60..=3_599 => format!("{}m ago", secs / 60),
+        3_600..=86_399 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum StatusFilter {
+    Any,
+    Succeeded,
+    Failed,
+}
+
+struct HistorySource<'a> {
+    entries: Vec<HistoryEntry>,
+    session: &'a str,
+    cwd: &'a Path,
+    this_session: bool,   // Ctrl+S
+    this_dir: bool,       // Ctrl+D
+    status: StatusFilter, // Ctrl+E cycles any, succeeded, failed
+}
+
+impl HistorySource<'_> {
+    fn passes_filters(&self, entry: &HistoryEntry) -> bool {
+        (!self.this_session || entry.session == self.session)
+            && (!self.this_dir || entry.cwd == self.cwd)
+            && match self.status {
+                StatusFilter::Any => true,
+                StatusFilter::Succeeded => entry.success,
+                StatusFilter::Failed => !entry.success,
+            }
+    }
+
+    fn filter_summary(&self) -> String {
+        let mut filters = Vec::new();
+        if self.this_session {
+            filters.push(format!("session {}", self.session));
+        }
+        if self.this_dir {
+            filters.push("this directory".to_string());
+        }
+        match self.status {
+            StatusFilter::Any => {}
+            StatusFilter::Succeeded => filters.push("succeeded".to_string()),
+            StatusFilter::Failed => filters.push("failed".to_string()),
+        }
+        if filters.is_empty() { "all".to_string() } else { filters.join(", ") }
+    }
+}
+
+impl PickerSource for HistorySource<'_> {
+    fn text(&self, index: usize) -> &str {
+        &self.entries[index].command
+    }
+
+    // Newest first, so among equal matches the most recent wins; repeats are offered once
+    fn filtered(&self) -> Vec<usize> {
+        let mut seen = HashSet::new();
+        self.entries.iter()
+            .enumerate()
+            .filter(|(_, entry)| self.passes_filters(entry) && seen.insert(entry.command.as_str()))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn status(&self, matches: usize) -> String {
+        format!("  {}  [{}]  ^S session  ^D directory  ^E status", matches, self.filter_summary())
+    }
+
+    fn decorate(&self, index: usize) -> (ColoredString, ColoredString) {
+        let entry = &self.entries[index];
+        let marker = if entry.success { "✓ ".green() } else { "✗ ".red() };
+        (marker, format!("  {} · {}", entry.session, ago(entry.time)).dimmed())
+    }
+
+    fn handle_key(&mut self, code: KeyCode, ctrl: bool) -> KeyAction {
+        match code {
+            KeyCode::Char('r') if ctrl => return KeyAction::Move(-1),
+            KeyCode::Char('s') if ctrl => self.this_session = !self.this_session,
+            KeyCode::Char('d') if ctrl => self.this_dir = !self.this_dir,
+            KeyCode::Char('e') if ctrl => {
+                self.status = match self.status {
+                    StatusFilter::Any => StatusFilter::Succeeded,
+                    StatusFilter::Succeeded => StatusFilter::Failed,
+                    StatusFilter::Failed => StatusFilter::Any,
+                };
+            }
+            _ => return KeyAction::Ignore,
+        }
+        KeyAction::Refilter
+    }
+}
+
+// Full-screen fuzzy search through past commands, starting from `query`
+pub fn pick(query: &str, session: &str, cwd: &Path) -> io::Result<Option<String>> {
+    let source = HistorySource {
+        entries: load(),
+        session,
+        cwd,
+        this_session: false,
+        this_dir: false,
+        status: StatusFilter::Any,
+    };
+    picker::pick(source, query)
+}
+
+// Ctrl+R in the line editor: search the history with whatever is typed so far and
+// replace the line with the chosen command, ready for editing
+pub struct HistoryKey {
+    pub session: String,
+    pub dir: Arc<Mutex<PathBuf>>,
+}
+
+impl ConditionalEventHandler for HistoryKey {
+    fn handle(&self, _: &rustyline::Event, _: RepeatCount, _: bool, ctx: &EventContext) -> Option<Cmd> {
+        let dir = self.dir.lock().ok()?.clone();
+        match pick(ctx.line(), &self.session, &dir) {
+            Ok(Some(command)) => Some(Cmd::Replace(Movement::WholeBuffer, Some(command))),
+            _ => Some(Cmd::Noop),
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::Path;
+use unicode_width::UnicodeWidthStr;
+use crate::config::LsConfig;
+use crate::git::GitRepo;
+use crate::Environment;
+
+// Used when LS_COLORS is not set; a trimmed-down version of the GNU dircolors defaults
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:or=40;31;01:mi=00:pi=40;33:so=01;35:\
+bd=40;33;01:cd=40;33;01:ex=01;32:su=37;41:sg=30;43:tw=30;42:ow=34;42:st=37;44:\
+*.tar=01;31:*.tgz=01;31:*.gz=01;31:*.bz2=01;31:*.xz=01;31:*.zst=01;31:*.zip=01;31:\
+*.7z=01;31:*.rar=01;31:*.deb=01;31:*.rpm=01;31:*.jar=01;31:*.tbz2=01;31:*.txz=01;31:\
+*.jpg=01;35:*.jpeg=01;35:*.png=01;35:*.gif=01;35:*.bmp=01;35:*.svg=01;35:\
+*.webp=01;35:*.ico=01;35:*.tif=01;35:*.tiff=01;35:*.heic=01;35";
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "tar", "tgz", "gz", "bz2", "xz", "zst", "zip", "7z", "rar", "deb", "rpm", "jar", "tbz2", "txz",
+];
+const IMAGE_EXTENSIONS: &[&str] = &[
+    "jpg", "jpeg", "png", "gif", "bmp", "svg", "webp", "ico", "tif", "tiff", "heic",
+];
+
+// What kind of file an entry is, as far as coloring is concerned
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Directory,
+    Symlink,
+    BrokenSymlink,
+    Socket,
+    Pipe,
+    BlockDevice,
+    CharDevice,
+    Executable,
+    File,
+}
+
+impl FileKind {
+    pub fn of(path: &Path) -> Self {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return FileKind::BrokenSymlink;
+        };
+        let file_type = metadata.file_type();
+
+        if file_type.is_symlink() {
+            return if fs::metadata(path).is_ok() {
+                FileKind::Symlink
+            } else {
+                FileKind::BrokenSymlink
+            };
+        }
+        if file_type.is_dir() {
+            return FileKind::Directory;
+        }
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+            if file_type.is_socket() {
+                return FileKind::Socket;
+            } else if file_type.is_fifo() {
+                return FileKind::Pipe;
+            } else if file_type.is_block_device() {
+                return FileKind::BlockDevice;
+            } else if file_type.is_char_device() {
+                return FileKind::CharDevice;
+            } else if metadata.permissions().mode() & 0o111 != 0 {
+                return FileKind::Executable;
+            }
+        }
+
+        FileKind::File
+    }
+
+    // The two-letter LS_COLORS key for this kind
+    fn key(self) -> &'static str {
+        match self {
+            FileKind::Directory => "di",
+            FileKind::Symlink => "ln",
+            FileKind::BrokenSymlink => "or",
+            FileKind::Socket => "so",
+            FileKind::Pipe => "pi",
+            FileKind::BlockDevice => "bd",
+            FileKind::CharDevice => "cd",
+            FileKind::Executable => "ex",
+            FileKind::File => "fi",
+        }
+    }
+}
+
+// Parsed LS_COLORS: SGR sequences keyed by file kind and by extension
+pub struct LsColors {
+    kinds: HashMap<String, String>,
+    extensions: HashMap<String, String>,
+}
+
+impl LsColors {
+    pub fn from_env() -> Self {
+        match env::var("LS_COLORS") {
+            Ok(spec) if !spec.is_empty() => Self::parse(&spec),
+            _ => Self::parse(DEFAULT_LS_COLORS),
+        }
+    }
+
+    fn parse(spec: &str) -> Self {
+        let mut kinds = HashMap::new();
+        let mut extensions = HashMap::new();
+
+        for item in spec.split(':') {
+            let Some((key, value)) = item.split_once('=') else { continue };
+            if let Some(ext) = key.strip_prefix("*.") {
+                extensions.insert(ext.to_lowercase(), value.to_string());
+            } else {
+                kinds.insert(key.to_string(), value.to_string());
+            }
+        }
+
+        Self { kinds, extensions }
+    }
+
+    fn style_for(&self, name: &str, kind: FileKind) -> Option<&str> {
+        // Like GNU ls, extensions only apply to plain files
+        if kind == FileKind::File {
+            if let Some(style) = extension(name).and_then(|ext| self.extensions.get(&ext)) {
+                return Some(style);
+            }
+        }
+        self.kinds.get(kind.key()).map(String::as_str)
+    }
+
+    pub fn paint(&self, name: &str, kind: FileKind) -> String {
+        match self.style_for(name, kind) {
+            Some(style) if !style.is_empty() && style != "0" && style != "00" => {
+                format!("\x1B[{}m{}\x1B[0m", style, name)
+            }
+            _ => name.to_string(),
+        }
+    }
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+}
+
+// Nerd Font glyph for an entry, picked by extension first and kind second
+pub fn icon_for(name: &str, kind: FileKind) -> &'static str {
+    match kind {
+        FileKind::Directory => return "\u{f115}",
+        FileKind::Symlink => return "\u{f481}",
+        FileKind::BrokenSymlink => return "\u{f127}",
+        FileKind::Socket => return "\u{f1e6}",
+        FileKind::Pipe => return "\u{f124}",
+        FileKind::BlockDevice | FileKind::CharDevice => return "\u{f0a0}",
+        FileKind::Executable | FileKind::File => {}
+    }
+
+    let ext = extension(name).unwrap_or_default();
+    if ARCHIVE_EXTENSIONS.contains(&ext.as_str()) {
+        return "\u{f410}";
+    }
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        return "\u{f1c5}";
+    }
+
+    match ext.as_str() {
+        "rs" => "\u{e7a8}",
+        "py" => "\u{e606}",
+        "js" | "mjs" | "cjs" => "\u{e74e}",
+        "ts" => "\u{e628}",
+        "go" => "\u{e626}",
+        "c" | "h" => "\u{e61e}",
+        "cpp" | "cc" | "hpp" => "\u{e61d}",
+        "java" => "\u{e738}",
+        "rb" => "\u{e21e}",
+        "sh" | "bash" | "zsh" | "fish" => "\u{f489}",
+        "md" => "\u{f48a}",
+        "json" => "\u{e60b}",
+        "toml" | "yaml" | "yml" | "ini" | "conf" => "\u{e615}",
+        "html" | "htm" => "\u{f13b}",
+        "css" | "scss" => "\u{e749}",
+        "lock" => "\u{f023}",
+        "txt" | "log" => "\u{f15c}",
+        "pdf" => "\u{f1c1}",
+        _ if kind == FileKind::Executable => "\u{f489}",
+        _ => "\u{f15b}",
+    }
+}
+
+// How `ls` arranges entries on screen
+#[derive(Clone, Copy, PartialEq)]
+pub enum Layout {
+    Columns,    // -C: packed into columns, top to bottom then left to right
+    OnePerLine, // -1
+}
+
+#[derive(Default)]
+pub struct LsOptions {
+    pub show_hidden: bool,
+    pub layout: Option<Layout>, // None picks columns on a TTY and one-per-line otherwise
+    pub git: bool,              // --git: annotate entries with their git status
+}
+
+// Spaces between columns
+const COLUMN_GAP: usize = 2;
+
+// Width available for column output; falls back to 80 like GNU ls
+fn terminal_width() -> usize {
+    crossterm::terminal::size()
+        .map(|(cols, _)| cols as usize)
+        .unwrap_or(80)
+}
+
+// Finds the largest number of columns whose combined width fits, GNU-style
+fn column_widths(widths: &[usize], line_width: usize) -> (usize, Vec<usize>) {
+    let count = widths.len();
+    // Every column holds at least one character plus the gap
+    let max_cols = count.min((line_width / (1 + COLUMN_GAP)).max(1));
+
+    for cols in (1..=max_cols).rev() {
+        let rows = count.div_ceil(cols);
+        // Skip counts that would leave trailing columns empty
+        if cols > 1 && (cols - 1) * rows >= count {
+            continue;
+        }
+        let col_widths: Vec<usize> = (0..cols)
+            .map(|c| widths[c * rows..((c + 1) * rows).min(count)].iter().copied().max().unwrap_or(0))
+            .collect();
+        let total = col_widths.iter().sum::<usize>() + COLUMN_GAP * (cols - 1);
+        if total <= line_width || cols == 1 {
+            return (rows, col_widths);
+        }
+    }
+    (count, vec![0])
+}
+
+// The lines of `cells` laid out in columns, filled top to bottom
+fn column_lines(cells: &[(String, usize)], line_width: usize) -> Vec<String> {
+    if cells.is_empty() {
+        return Vec::new();
+    }
+    let widths: Vec<usize> = cells.iter().map(|(_, width)| *width).collect();
+    let (rows, col_widths) = column_widths(&widths, line_width);
+
+    (0..rows).map(|row| {
+        let mut line = String::new();
+        for (col, col_width) in col_widths.iter().enumerate() {
+            let Some((text, width)) = cells.get(col * rows + row) else { break };
+            line.push_str(text);
+            // Pad unless this is the last entry on the line
+            if cells.get((col + 1) * rows + row).is_some() {
+                line.push_str(&" ".repeat(col_width - width + COLUMN_GAP));
+            }
+        }
+        line
+    }).collect()
+}
+
+fn print_columns(cells: &[(String, usize)], line_width: usize) {
+    for line in column_lines(cells, line_width) {
+        println!("{}", line);
+    }
+}
+
+pub fn execute_ls(path: Option<&str>, env: &Environment, options: &LsOptions, config: &LsConfig) -> io::Result<()> {
+    let target_path = path.map_or_else(|| env.current_dir.clone(), |p| env.resolve_path(p));
+
+    let entries = fs::read_dir(&target_path)?;
+    let mut files: Vec<_> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name_str = name.to_string_lossy();
+            options.show_hidden || !name_str.starts_with('.')
+        })
+        .collect();
+
+    files.sort_by_key(|entry| entry.file_name());
+
+    let colors = LsColors::from_env();
+    let repo = if options.git || config.git { GitRepo::discover(&target_path) } else { None };
+    let cells: Vec<(String, usize)> = files
+        .iter()
+        .map(|entry| {
+            let name = entry.file_name();
+            let name_str = name.to_string_lossy();
+            let entry_path = entry.path();
+            let kind = FileKind::of(&entry_path);
+            let status = repo.as_ref().map(|repo| repo.status_of(&entry_path));
+
+            let mut width = name_str.width();
+            let mut text = match status {
+                Some(status) if status.is_ignored() => format!("\x1B[2m{}\x1B[0m", name_str),
+                _ => colors.paint(&name_str, kind),
+            };
+            if config.icons {
+                text = format!("{} {}", icon_for(&name_str, kind), text);
+                width += 2;
+            }
+            if let Some(status) = status {
+                text = format!("{} {}", status.marker(), text);
+                width += 3;
+            }
+            (text, width)
+        })
+        .collect();
+
+    let is_tty = io::stdout().is_terminal();
+    let layout = options.layout.unwrap_or(if is_tty { Layout::Columns } else { Layout::OnePerLine });
+    match layout {
+        Layout::OnePerLine => {
+            for (text, _) in &cells {
+                println!("{}", text);
+            }
+        }
+        Layout::Columns => print_columns(&cells, if is_tty { terminal_width() } else { 80 }),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_kinds_and_extensions() {
+        let colors = LsColors::parse("di=01;34:ex=01;32:*.TAR=01;31:bogus:*.md=");
+        assert_eq!(colors.style_for("src", FileKind::Directory), Some("01;34"));
+        assert_eq!(colors.style_for("a.tar", FileKind::File), Some("01;31"));
+        assert_eq!(colors.style_for("notes.md", FileKind::File), Some(""));
+        assert_eq!(colors.style_for("plain", FileKind::File), None);
+    }
+
+    #[test]
+    fn extensions_only_color_plain_files() {
+        let colors = LsColors::parse("ex=01;32:*.sh=00;33");
+        assert_eq!(colors.style_for("run.sh", FileKind::File), Some("00;33"));
+        assert_eq!(colors.style_for("run.sh", FileKind::Executable), Some("01;32"));
+    }
+
+    #[test]
+    fn paint_skips_empty_and_reset_styles() {
+        let colors = LsColors::parse("di=01;34:fi=0");
+        assert_eq!(colors.paint("src", FileKind::Directory), "\x1B[01;34msrc\x1B[0m");
+        assert_eq!(colors.paint("a.txt", FileKind::File), "a.txt");
+    }
+
+    fn cells(names: &[&str]) -> Vec<(String, usize)> {
+        names.iter().map(|name| (name.to_string(), name.len())).collect()
+    }
+
+    #[test]
+    fn columns_of_nothing_are_empty() {
+        assert_eq!(column_widths(&[], 80), (0, vec![0]));
+        assert!(column_lines(&[], 80).is_empty());
+    }
+
+    #[test]
+    fn entry_wider_than_the_line_gets_one_column() {
+        assert_eq!(column_widths(&[100], 80), (1, vec![100]));
+        assert_eq!(column_widths(&[100, 3, 3], 80), (3, vec![100]));
+        let wide = "x".repeat(100);
+        assert_eq!(column_lines(&cells(&[&wide, "a", "b"]), 80), vec![wide.clone(), "a".to_string(), "b".to_string()]);
+    }
+
+    #[test]
+    fn last_column_may_be_shorter() {
+        let names = ["aaa", "bbb", "ccc", "ddd", "eee"];
+        assert_eq!(column_widths(&[3; 5], 14), (2, vec![3, 3, 3]));
+        assert_eq!(column_lines(&cells(&names), 14), vec!["aaa  ccc  eee", "bbb  ddd"]);
+    }
+
+    #[test]
+    fn columns_are_as_wide_as_their_widest_entry() {
+        assert_eq!(column_lines(&cells(&["a", "bbbb", "cc", "d"]), 12), vec!["a     cc", "bbbb  d"]);
+    }
+}
@@ -1,33 +1,72 @@
+mod args;
+mod bookmarks;
+mod bundle;
+mod cast;
+mod cat;
+mod completion;
+mod config;
+#[cfg(unix)]
+mod daemon;
+mod external;
+mod fileops;
+mod find;
+mod finder;
+mod frecency;
+mod git;
+mod history;
+mod ls;
+#[cfg(unix)]
+mod mux;
+mod pager;
+mod picker;
+#[cfg(unix)]
+mod pty;
+mod text;
+mod timing;
+mod trash;
+mod tree;
+mod which;
+
 use std::io::{self, Write};
-use std::fs::File;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
 use colored::*;
-use syntect::highlighting::{ThemeSet, Style};
-use syntect::easy::HighlightLines;
+use syntect::highlighting::ThemeSet;
 use syntect::parsing::SyntaxSet;
-use syntect::util::as_24_bit_terminal_escaped;
 use chrono::{DateTime, Local};
-use rustyline::Editor;
+use rustyline::{Editor, EventHandler, KeyEvent};
 use rustyline::history::FileHistory;
 use dirs::home_dir;
 use rand::seq::SliceRandom;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 use std::env;
 use std::fs;
-use std::process::Command;
 use serde::{Deserialize, Serialize};
 use dotenv::dotenv;
-use reqwest;
-
-// Enum to represent different functionalities of Partermai
-enum ToolMode {
-    Shell,
-}
+use bookmarks::{execute_jump, execute_mark, execute_marks, execute_unmark};
+use cat::{execute_cat, CatOptions};
+use completion::ShellHelper;
+use config::{Config, ProfileConfig};
+use fileops::{execute_cp, execute_mkdir, execute_mv, execute_rm, execute_touch, FileOperation};
+use find::execute_find;
+use finder::FinderKey;
+use history::HistoryKey;
+use frecency::execute_z;
+use ls::{execute_ls, Layout, LsOptions};
+use text::{execute_grep, execute_head, execute_sort, execute_tail, execute_uniq, execute_wc};
+use trash::execute_trash;
+use tree::{execute_tree, TreeOptions};
+use which::{execute_type, execute_which};
+use pager::Pager;
 
 #[derive(Default)]
 struct Environment {
     current_dir: PathBuf,
     home_dir: PathBuf,
+    previous_dir: Option<PathBuf>, // For `cd -`
+    dir_stack: Vec<PathBuf>,       // pushd/popd stack, top first, not including current_dir
+    vars: BTreeMap<String, String>,    // Extra environment variables for the programs the session runs
+    aliases: BTreeMap<String, String>, // Command names that stand for longer command lines
 }
 
 impl Environment {
@@ -35,6 +74,10 @@ impl Environment {
         Self {
             current_dir: env::current_dir().unwrap_or_else(|_| PathBuf::from("/")),
             home_dir: dirs::home_dir().unwrap_or_else(|| PathBuf::from("/")),
+            previous_dir: None,
+            dir_stack: Vec::new(),
+            vars: BTreeMap::new(),
+            aliases: BTreeMap::new(),
         }
     }
 
@@ -42,23 +85,170 @@ impl Environment {
         self.current_dir.display().to_string()
     }
 
-    fn change_directory(&mut self, path: &str) -> io::Result<()> {
-        let new_path = if path == "~" {
+    // Shortens paths under the home directory to ~/...
+    fn tilde_path(&self, path: &Path) -> String {
+        match path.strip_prefix(&self.home_dir) {
+            Ok(rest) if rest.as_os_str().is_empty() => String::from("~"),
+            Ok(rest) => format!("~/{}", rest.display()),
+            Err(_) => path.display().to_string(),
+        }
+    }
+
+    // Expands ~ and joins `path` to this session's directory. `..` is left for the kernel,
+    // so file commands see the same file any other program would through a symlink.
+    fn resolve_path(&self, path: &str) -> PathBuf {
+        if path == "~" {
             self.home_dir.clone()
-        } else if path.starts_with("~/") {
-            self.home_dir.join(&path[2..])
+        } else if let Some(rest) = path.strip_prefix("~/") {
+            self.home_dir.join(rest)
+        } else {
+            self.current_dir.join(path)
+        }
+    }
+
+    // `cd`: logical by default (`..` drops the last component), physical resolves symlinks
+    fn change_directory(&mut self, path: &str, physical: bool) -> io::Result<()> {
+        let new_path = if path == "-" {
+            self.previous_dir.clone()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No previous directory"))?
         } else {
-            Path::new(path).to_path_buf()
+            match self.search_cdpath(path) {
+                Some(found) => {
+                    // Like other shells, say where CDPATH took us
+                    println!("{}", found.display());
+                    found
+                }
+                None => normalize_path(&self.resolve_path(path)),
+            }
         };
 
+        let new_path = if physical { new_path.canonicalize()? } else { new_path };
+        self.set_directory(new_path)
+    }
+
+    // Looks `path` up in $CDPATH unless it is absolute or explicitly relative
+    fn search_cdpath(&self, path: &str) -> Option<PathBuf> {
+        let explicit = path.starts_with('/') || path.starts_with('~')
+            || path == "." || path == ".." || path.starts_with("./") || path.starts_with("../");
+        if explicit || self.current_dir.join(path).is_dir() {
+            return None;
+        }
+
+        let cdpath = env::var("CDPATH").ok()?;
+        cdpath.split(':')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| normalize_path(&self.resolve_path(entry).join(path)))
+            .find(|candidate| candidate.is_dir())
+    }
+
+    fn set_directory(&mut self, new_path: PathBuf) -> io::Result<()> {
         if new_path.is_dir() {
-            self.current_dir = new_path;
-            env::set_current_dir(&self.current_dir)?;
+            env::set_current_dir(&new_path)?;
+            self.previous_dir = Some(std::mem::replace(&mut self.current_dir, new_path));
+            frecency::record_visit(&self.current_dir);
             Ok(())
         } else {
             Err(io::Error::new(io::ErrorKind::NotFound, "Directory not found"))
         }
     }
+
+    // The current directory followed by the stack, as `dirs` shows it
+    fn directory_stack(&self) -> Vec<PathBuf> {
+        std::iter::once(self.current_dir.clone())
+            .chain(self.dir_stack.iter().cloned())
+            .collect()
+    }
+
+    // Makes entry 0 of `entries` the current directory and the rest the stack
+    fn apply_directory_stack(&mut self, mut entries: Vec<PathBuf>) -> io::Result<()> {
+        let target = entries.remove(0);
+        if target != self.current_dir {
+            self.set_directory(target)?;
+        }
+        self.dir_stack = entries;
+        Ok(())
+    }
+
+    // pushd <dir>, pushd (swap the top two) or pushd +N (rotate)
+    fn push_directory(&mut self, arg: Option<&str>) -> io::Result<()> {
+        let Some(arg) = arg else {
+            let mut entries = self.directory_stack();
+            if entries.len() < 2 {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "No other directory"));
+            }
+            entries.swap(0, 1);
+            return self.apply_directory_stack(entries);
+        };
+
+        match arg.strip_prefix('+').and_then(|n| n.parse::<usize>().ok()) {
+            Some(n) => {
+                let mut entries = self.directory_stack();
+                if n >= entries.len() {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "Directory stack index out of range"));
+                }
+                entries.rotate_left(n);
+                self.apply_directory_stack(entries)
+            }
+            None => {
+                let previous = self.current_dir.clone();
+                self.change_directory(arg, false)?;
+                self.dir_stack.insert(0, previous);
+                Ok(())
+            }
+        }
+    }
+
+    // popd or popd +N
+    fn pop_directory(&mut self, arg: Option<&str>) -> io::Result<()> {
+        let n = match arg {
+            None => 0,
+            Some(arg) => arg.strip_prefix('+')
+                .and_then(|n| n.parse::<usize>().ok())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Usage: popd [+N]"))?,
+        };
+        let mut entries = self.directory_stack();
+        if entries.len() < 2 {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Directory stack empty"));
+        }
+        if n >= entries.len() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "Directory stack index out of range"));
+        }
+        entries.remove(n);
+        self.apply_directory_stack(entries)
+    }
+
+    fn print_directory_stack(&self, verbose: bool) {
+        let entries = self.directory_stack();
+        if verbose {
+            for (i, dir) in entries.iter().enumerate() {
+                println!("{:>2}  {}", i.to_string().bright_cyan(), self.tilde_path(dir));
+            }
+        } else {
+            let line: Vec<String> = entries.iter().map(|dir| self.tilde_path(dir)).collect();
+            println!("{}", line.join(" "));
+        }
+    }
+}
+
+// Errors are reported the same way everywhere and mark the command as failed in the history
+fn print_error(e: impl std::fmt::Display) {
+    println!("{}: {}", "Error".red(), e);
+    history::mark_failed();
+}
+
+// Removes `.` and resolves `..` lexically, the way a logical `cd` sees the path
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
 }
 
 // Struct to represent a session
@@ -67,6 +257,11 @@ struct Session {
     history: Vec<String>, // To store command history per session
     env: Environment,
     voia: Option<Voia>,
+    undo_stack: Vec<FileOperation>, // Destructive file operations, most recent last
+    startup: Vec<String>,           // Commands to run when the session is first shown
+    voia_persona: Option<String>,   // How Voia should behave in this session
+    profile: Option<String>,        // The config profile the session was made from
+    editor_history: Vec<String>,    // Imported commands for the line editor when the session is first shown
 }
 
 impl Session {
@@ -76,6 +271,36 @@ impl Session {
             history: Vec::new(),
             env: Environment::new(),
             voia: None,
+            undo_stack: Vec::new(),
+            startup: Vec::new(),
+            voia_persona: None,
+            profile: None,
+            editor_history: Vec::new(),
+        }
+    }
+
+    // A session set up by a profile from the config instead of starting blank
+    fn from_profile(name: String, profile_name: &str, profile: &ProfileConfig) -> io::Result<Self> {
+        let mut session = Self::new(name);
+        session.profile = Some(profile_name.to_string());
+        if let Some(cwd) = &profile.cwd {
+            let dir = normalize_path(&session.env.resolve_path(cwd));
+            session.env.set_directory(dir).map_err(|e| io::Error::new(e.kind(), format!("{}: {}", cwd, e)))?;
+            session.env.previous_dir = None;
+        }
+        session.env.vars = profile.env.clone();
+        session.env.aliases = profile.aliases.clone();
+        session.startup = profile.startup.clone();
+        session.voia_persona = profile.voia_persona.clone();
+        Ok(session)
+    }
+
+    // Replaces an alias at the start of `input` with what it stands for
+    fn expand_alias(&self, input: &str) -> String {
+        let (first, rest) = input.split_once(char::is_whitespace).unwrap_or((input, ""));
+        match self.env.aliases.get(first) {
+            Some(expansion) => format!("{} {}", expansion, rest),
+            None => input.to_string(),
         }
     }
 
@@ -84,8 +309,8 @@ impl Session {
             "💡 Use 'clear' to clean the terminal",
             "💡 Try 'help' to see all available commands",
             "💡 You can switch sessions with 'switch <name>'",
-            "💡 Use 'edit' to open the text editor",
-            "💡 Press Ctrl+R to search through command history",
+            "💡 Press Ctrl+R to fuzzy-search your command history",
+            "💡 Press Ctrl+T to fuzzy-find a file and insert its path",
             "💡 Use Tab for command completion",
         ];
         tips.choose(&mut rand::thread_rng()).unwrap()
@@ -96,10 +321,25 @@ impl Session {
     }
 }
 
+// What the session manager should do after a `partermai` command
+enum SessionAction {
+    Stay,
+    New { name: String, profile: Option<String> },
+    Switch(String),
+    List,
+    Close(String),
+    Export { name: String, path: PathBuf },
+    Import(PathBuf),
+    Mux(Vec<String>),
+    Attach { name: String, profile: Option<String> },
+    End,
+}
+
 // Session manager to handle multiple sessions
 struct SessionManager {
     sessions: HashMap<String, Session>,
     active_session: Option<String>, // Name of the current active session
+    config: Config,
 }
 
 impl SessionManager {
@@ -107,56 +347,129 @@ impl SessionManager {
         Self {
             sessions: HashMap::new(),
             active_session: None,
+            config: Config::load(),
         }
     }
 
-    fn create_session(&mut self, name: &str) {
+    fn create_session(&mut self, name: &str) -> bool {
         if self.sessions.contains_key(name) {
             println!("{}", "Session with this name already exists!".red());
+            false
         } else {
             let session = Session::new(name.to_string());
             self.sessions.insert(name.to_string(), session);
             println!("{}: {}", "New session created".green(), name);
+            true
+        }
+    }
+
+    fn create_session_from_profile(&mut self, name: &str, profile_name: &str) -> bool {
+        if self.sessions.contains_key(name) {
+            println!("{}", "Session with this name already exists!".red());
+            return false;
+        }
+        let Some(profile) = self.config.profiles.get(profile_name) else {
+            print_error(format!("No profile named {} in the config", profile_name));
+            return false;
+        };
+        match Session::from_profile(name.to_string(), profile_name, profile) {
+            Ok(session) => {
+                self.sessions.insert(name.to_string(), session);
+                println!("{}: {} (profile {})", "New session created".green(), name, profile_name.yellow());
+                true
+            },
+            Err(e) => {
+                print_error(format!("Profile {}: {}", profile_name, e));
+                false
+            },
         }
     }
 
     fn list_sessions(&self) {
         println!("Active Sessions:");
-        for name in self.sessions.keys() {
-            println!("{}", name.yellow());
+        let mut names: Vec<&String> = self.sessions.keys().collect();
+        names.sort();
+        for name in names {
+            let marker = if self.active_session.as_ref() == Some(name) { "*" } else { " " };
+            println!("{} {}", marker, name.yellow());
+        }
+    }
+
+    // A session handed over by another Partermai as a bundle file, which is used up
+    fn create_session_from_bundle(&mut self, name: &str, path: &Path, profile: Option<String>) -> bool {
+        let result = bundle::read(path).and_then(|bundle| bundle::restore(bundle, name.to_string(), &mut io::stdout()));
+        let _ = fs::remove_file(path);
+        match result {
+            Ok(mut session) => {
+                session.profile = profile;
+                self.sessions.insert(name.to_string(), session);
+                true
+            },
+            Err(e) => {
+                print_error(e);
+                false
+            },
         }
     }
 
     fn switch_session(&mut self, name: &str) {
-        if self.sessions.contains_key(name) {
+        if let Some(session) = self.sessions.get(name) {
             self.active_session = Some(name.to_string());
+            // Sessions share the process, so its directory follows the active one
+            let _ = env::set_current_dir(&session.env.current_dir);
             println!("{}: {}", "Switched to session".green(), name);
         } else {
             println!("{}", "Session not found!".red());
         }
     }
 
+    // `name`, or `name-2`, `name-3`, ... if a session already has it
+    fn unused_name(&self, name: &str) -> String {
+        std::iter::once(name.to_string())
+            .chain((2..).map(|n| format!("{}-{}", name, n)))
+            .find(|candidate| !self.sessions.contains_key(candidate))
+            .unwrap_or_default()
+    }
+
+    // Closes a session other than the active one, which ends with `exit`
     fn close_session(&mut self, name: &str) {
-        if let Some(session) = self.sessions.remove(name) {
+        if self.active_session.as_deref() == Some(name) {
+            print_error("Use 'exit' to close the session you are in");
+        } else if self.sessions.remove(name).is_some() {
             println!("{}: {}", "Closed session".green(), name);
-            session.print_goodbye();
+        } else {
+            println!("{}", "Session not found!".red());
         }
     }
 
-    async fn run_active_session(&mut self, ps: &SyntaxSet, ts: &ThemeSet) {
+    // Runs the active session until it ends or a `partermai` command hands over to another
+    // session. Returns false once there is no session left to run.
+    async fn run_active_session(&mut self, ps: &SyntaxSet, ts: &ThemeSet) -> bool {
+        let mut next = None;
         if let Some(session_name) = &self.active_session {
             if let Some(session) = self.sessions.get_mut(session_name) {
-                let mut editor = Editor::<(), FileHistory>::new().unwrap();
+                let mut editor = Editor::<ShellHelper, FileHistory>::new().unwrap();
+                editor.set_helper(Some(ShellHelper::new(&self.config.bookmarks)));
                 if let Some(home) = home_dir() {
                     let history_path = home.join(".partermai_history");
                     editor.load_history(&history_path).unwrap_or_default();
                 }
-                
-                let syntax = ps.find_syntax_by_extension("sh").unwrap();
-                let mut h = HighlightLines::new(syntax, &ts.themes["base16-ocean.dark"]);
-
-                // Show a random tip at start
-                println!("{}", Session::get_random_tip().bright_yellow());
+                for command in session.editor_history.drain(..) {
+                    let _ = editor.add_history_entry(command);
+                }
+                // Ctrl+T opens the fuzzy file finder and Ctrl+R the history search, both
+                // looking at whatever directory the session is in
+                let key_dir = Arc::new(Mutex::new(session.env.current_dir.clone()));
+                editor.bind_sequence(KeyEvent::ctrl('T'), EventHandler::Conditional(Box::new(FinderKey { dir: key_dir.clone() })));
+                editor.bind_sequence(KeyEvent::ctrl('R'), EventHandler::Conditional(Box::new(HistoryKey {
+                    session: session_name.clone(),
+                    dir: key_dir.clone(),
+                })));
+                // Text to start the next input line with, left and right of the cursor
+                let mut initial_line: Option<(String, String)> = None;
+                // Commands taken instead of reading a line: the profile's startup commands,
+                // then any correction the user agreed to run
+                let mut queued_commands: VecDeque<String> = session.startup.drain(..).collect();
 
                 loop {
                     let prompt = format!("{}@{}:{} ❯ ", 
@@ -164,65 +477,267 @@ impl SessionManager {
                         session_name.bright_blue(),
                         session.env.get_current_dir_display().bright_green());
                     
-                    match editor.readline(&prompt) {
+                    if let Ok(mut dir) = key_dir.lock() {
+                        dir.clone_from(&session.env.current_dir);
+                    }
+                    let line = match (queued_commands.pop_front(), initial_line.take()) {
+                        (Some(command), _) => {
+                            println!("{}{}", prompt, command);
+                            Ok(command)
+                        },
+                        (None, Some((left, right))) => editor.readline_with_initial(&prompt, (&left, &right)),
+                        (None, None) => editor.readline(&prompt),
+                    };
+
+                    match line {
                         Ok(input) => {
                             let input = input.trim();
                             if input.is_empty() { continue; }
                             
                             let _ = editor.add_history_entry(input.to_string());
                             session.history.push(input.to_string());
+                            let cwd = session.env.current_dir.clone();
 
-                            let parts: Vec<&str> = input.split_whitespace().collect();
+                            // Aliases expand first; arguments may refer to bookmarks as @name or @name/sub/path
+                            let expanded = session.expand_alias(input);
+                            let words: Vec<String> = args::split_words(&expanded).into_iter()
+                                .enumerate()
+                                .map(|(i, word)| if i == 0 { word } else { bookmarks::expand(&word, &self.config) })
+                                .collect();
+                            let parts: Vec<&str> = words.iter().map(String::as_str).collect();
+                            // `time <cmd>` runs the rest of the line as usual and reports on it afterwards
+                            let timed = parts.first() == Some(&"time");
+                            let parts = if timed { parts[1..].to_vec() } else { parts };
+                            let timer = timing::Timer::start();
+                            if timed && parts.is_empty() {
+                                print_error("Usage: time <command> [args]...");
+                            }
                             if let Some(cmd) = parts.first() {
                                 match *cmd {
-                                    "exit" | "quit" => {
-                                        session.print_goodbye();
-                                        break;
-                                    },
+                                    "exit" | "quit" => break,
                                     "clear" => print!("\x1B[2J\x1B[1;1H"),
                                     "tip" => println!("{}", Session::get_random_tip().bright_yellow()),
                                     "history" => {
-                                        for cmd in &session.history {
-                                            println!("{}", cmd);
+                                        let mut pager = Pager::new(&self.config.pager);
+                                        let result = session.history.iter()
+                                            .try_for_each(|cmd| writeln!(pager, "{}", cmd))
+                                            .and_then(|_| pager.finish());
+                                        if let Err(e) = result {
+                                            print_error(e);
                                         }
                                     },
                                     "ls" => {
-                                        let mut show_hidden = false;
+                                        let mut options = LsOptions::default();
                                         let mut path = None;
 
                                         for arg in &parts[1..] {
+                                            match arg.strip_prefix('-') {
+                                                Some("-git") => options.git = true,
+                                                Some(flags) if !flags.is_empty() => {
+                                                    for flag in flags.chars() {
+                                                        match flag {
+                                                            'a' => options.show_hidden = true,
+                                                            '1' => options.layout = Some(Layout::OnePerLine),
+                                                            'C' => options.layout = Some(Layout::Columns),
+                                                            _ => {}
+                                                        }
+                                                    }
+                                                }
+                                                _ => path = Some(*arg),
+                                            }
+                                        }
+
+                                        if let Err(e) = execute_ls(path, &session.env, &options, &self.config.ls) {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "tree" => {
+                                        let mut options = TreeOptions::default();
+                                        let mut path = None;
+                                        let mut bad_depth = false;
+                                        let mut args = parts[1..].iter();
+
+                                        while let Some(arg) = args.next() {
                                             match *arg {
-                                                "-a" => show_hidden = true,
-                                                _ if !arg.starts_with('-') => path = Some(*arg),
-                                                _ => {}
+                                                "-a" => options.show_hidden = true,
+                                                "-d" => options.dirs_only = true,
+                                                "-L" => match args.next().and_then(|d| d.parse().ok()) {
+                                                    Some(depth) if depth > 0 => options.max_depth = Some(depth),
+                                                    _ => bad_depth = true,
+                                                },
+                                                _ => path = Some(*arg),
                                             }
                                         }
 
-                                        if let Err(e) = execute_ls(path, &session.env, show_hidden) {
-                                            println!("{}: {}", "Error".red(), e);
+                                        if bad_depth {
+                                            println!("{}", "Usage: tree [-a] [-d] [-L depth] [path]".red());
+                                        } else if let Err(e) = execute_tree(path, &session.env, &options, &self.config.ls) {
+                                            print_error(e);
                                         }
                                     },
                                     "cd" => {
-                                        let path = parts.get(1).map_or("~", |s| *s);
-                                        if let Err(e) = session.env.change_directory(path) {
-                                            println!("{}: {}", "Error".red(), e);
+                                        let mut physical = false;
+                                        let mut path = "~";
+                                        for arg in &parts[1..] {
+                                            match *arg {
+                                                "-P" => physical = true,
+                                                "-L" => physical = false,
+                                                _ => path = arg,
+                                            }
+                                        }
+                                        if let Err(e) = session.env.change_directory(path, physical) {
+                                            print_error(e);
+                                        } else if path == "-" {
+                                            execute_pwd(&session.env);
+                                        }
+                                    },
+                                    "z" | "j" => {
+                                        if let Err(e) = execute_z(&parts[1..], &mut session.env) {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "mark" | "unmark" => {
+                                        let result = if *cmd == "mark" {
+                                            execute_mark(&parts[1..], &session.env, &mut self.config)
+                                        } else {
+                                            execute_unmark(&parts[1..], &mut self.config)
+                                        };
+                                        match result {
+                                            Ok(_) => {
+                                                if let Some(helper) = editor.helper_mut() {
+                                                    helper.set_bookmarks(&self.config.bookmarks);
+                                                }
+                                            },
+                                            Err(e) => print_error(e),
+                                        }
+                                    },
+                                    "jump" => {
+                                        if let Err(e) = execute_jump(&parts[1..], &mut session.env, &self.config) {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "marks" => execute_marks(&session.env, &self.config),
+                                    "pushd" => {
+                                        match session.env.push_directory(parts.get(1).copied()) {
+                                            Ok(_) => session.env.print_directory_stack(false),
+                                            Err(e) => print_error(e),
+                                        }
+                                    },
+                                    "popd" => {
+                                        match session.env.pop_directory(parts.get(1).copied()) {
+                                            Ok(_) => session.env.print_directory_stack(false),
+                                            Err(e) => print_error(e),
+                                        }
+                                    },
+                                    "dirs" => {
+                                        match parts.get(1).copied() {
+                                            Some("-c") => session.env.dir_stack.clear(),
+                                            Some("-v") => session.env.print_directory_stack(true),
+                                            _ => session.env.print_directory_stack(false),
                                         }
                                     },
                                     "pwd" => execute_pwd(&session.env),
                                     "cat" => {
-                                        if let Some(path) = parts.get(1) {
-                                            if let Err(e) = execute_cat(path, &session.env) {
-                                                println!("{}: {}", "Error".red(), e);
+                                        let mut options = CatOptions::default();
+                                        let mut paths = Vec::new();
+
+                                        for arg in &parts[1..] {
+                                            match *arg {
+                                                "-n" => options.number_lines = true,
+                                                "-p" => options.plain = true,
+                                                "-x" => options.hexdump = true,
+                                                _ => paths.push(*arg),
                                             }
+                                        }
+
+                                        if paths.is_empty() {
+                                            println!("{}", "Usage: cat [-n] [-p] [-x] <file>...".red());
                                         } else {
-                                            println!("{}", "Usage: cat <file>".red());
+                                            let mut pager = Pager::new(&self.config.pager);
+                                            let result = execute_cat(&paths, &session.env, &options, &self.config.cat, ps, ts, &mut pager);
+                                            // Show whatever was read before an error, then the error itself
+                                            if let Err(e) = pager.finish().and(result) {
+                                                print_error(e);
+                                            }
+                                        }
+                                    },
+                                    "find" => {
+                                        let mut out = io::stdout().lock();
+                                        if let Err(e) = execute_find(&parts[1..], &session.env, &mut out).and(out.flush()) {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "ff" => {
+                                        match finder::pick(&session.env.current_dir) {
+                                            // `ff vim` fills in `vim <path>`; plain `ff` leaves room to type the command
+                                            Ok(Some(path)) if parts.len() > 1 => {
+                                                let command: Vec<String> = parts[1..].iter().map(|word| args::quote(word)).collect();
+                                                initial_line = Some((format!("{} {}", command.join(" "), args::quote(&path)), String::new()));
+                                            },
+                                            Ok(Some(path)) => initial_line = Some((String::new(), format!(" {}", args::quote(&path)))),
+                                            Ok(None) => {},
+                                            Err(e) => print_error(e),
+                                        }
+                                    },
+                                    "grep" | "head" | "tail" | "wc" | "sort" | "uniq" => {
+                                        let mut out = io::stdout().lock();
+                                        let args = &parts[1..];
+                                        let result = match *cmd {
+                                            "grep" => execute_grep(args, &session.env, &mut out),
+                                            "head" => execute_head(args, &session.env, &mut out),
+                                            "tail" => execute_tail(args, &session.env, &mut out),
+                                            "wc" => execute_wc(args, &session.env, &mut out),
+                                            "sort" => execute_sort(args, &session.env, &mut out),
+                                            _ => execute_uniq(args, &session.env, &mut out),
+                                        };
+                                        if let Err(e) = out.flush().and(result) {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "mkdir" | "touch" => {
+                                        let result = if *cmd == "mkdir" {
+                                            execute_mkdir(&parts[1..], &session.env)
+                                        } else {
+                                            execute_touch(&parts[1..], &session.env)
+                                        };
+                                        if let Err(e) = result {
+                                            print_error(e);
+                                        }
+                                    },
+                                    "cp" | "mv" | "rm" => {
+                                        let result = match *cmd {
+                                            "cp" => execute_cp(&parts[1..], &session.env),
+                                            "mv" => execute_mv(&parts[1..], &session.env),
+                                            _ => execute_rm(&parts[1..], &session.env),
+                                        };
+                                        match result {
+                                            Ok(Some(mut operation)) => {
+                                                operation.command = input.to_string();
+                                                session.undo_stack.push(operation);
+                                            },
+                                            Ok(None) => {},
+                                            Err(e) => print_error(e),
+                                        }
+                                    },
+                                    "undo" => {
+                                        match session.undo_stack.pop() {
+                                            Some(operation) => match operation.undo() {
+                                                Ok(_) => println!("{}: {}", "Undid".green(), operation.command),
+                                                Err(e) => print_error(e),
+                                            },
+                                            None => println!("{}", "Nothing to undo.".yellow()),
+                                        }
+                                    },
+                                    "trash" => {
+                                        if let Err(e) = execute_trash(&parts[1..], &session.env) {
+                                            print_error(e);
                                         }
                                     },
                                     "voia" => {
                                         if session.voia.is_none() {
                                             match std::env::var("OPENAI_API_KEY") {
                                                 Ok(_) => {
-                                                    session.voia = Some(Voia::new());
+                                                    session.voia = Some(Voia::new(session.voia_persona.clone()));
                                                     println!("{}", "Voia AI Assistant is now active! Ask me anything...".bright_green());
                                                 },
                                                 Err(_) => {
@@ -237,8 +752,12 @@ impl SessionManager {
                                         if parts.len() > 1 {
                                             let question = parts[1..].join(" ");
                                             match session.voia.as_mut().unwrap().ask(&question).await {
-                                                Ok(response) => println!("{}: {}", "Voia".bright_cyan(), response),
-                                                Err(e) => println!("{}: {}", "Error".red(), e),
+                                                Ok(response) => {
+                                                    let mut pager = Pager::new(&self.config.pager);
+                                                    let _ = writeln!(pager, "{}: {}", "Voia".bright_cyan(), response)
+                                                        .and_then(|_| pager.finish());
+                                                },
+                                                Err(e) => print_error(e),
                                             }
                                         } else {
                                             println!("Usage: voia <your question>");
@@ -264,7 +783,7 @@ impl SessionManager {
                                                     Err(e) => println!("{}: {}", "Error saving API key".red(), e),
                                                 }
                                             } else {
-                                                session.voia = Some(Voia::new());
+                                                session.voia = Some(Voia::new(session.voia_persona.clone()));
                                                 if let Some(voia) = &mut session.voia {
                                                     if let Err(e) = voia.set_api_key(key) {
                                                         println!("{}: {}", "Error saving API key".red(), e);
@@ -297,27 +816,54 @@ impl SessionManager {
                                         }
                                     },
                                     cmd if cmd.starts_with("partermai") => {
-                                        handle_partermcli(input, session);
+                                        match handle_partermcli(&parts, session, &self.config) {
+                                            SessionAction::Stay => {},
+                                            action => next = Some(action),
+                                        }
                                     },
-                                    _ => {
-                                        // Try to execute as system command
-                                        if let Ok(mut child) = Command::new(cmd)
-                                            .args(&parts[1..])
-                                            .current_dir(&session.env.current_dir)
-                                            .spawn()
-                                        {
-                                            let _ = child.wait();
+                                    "type" | "which" => {
+                                        let mut out = io::stdout().lock();
+                                        let result = if *cmd == "type" {
+                                            execute_type(&parts[1..], &session.env, &mut out)
                                         } else {
-                                            println!("{}: command not found", cmd.red());
+                                            execute_which(&parts[1..], &session.env, &mut out)
+                                        };
+                                        if let Err(e) = out.flush().and(result) {
+                                            print_error(e);
                                         }
-                                    }
+                                    },
+                                    // Skips the builtins, so `command ls` runs the system ls
+                                    "command" => match parts.get(1) {
+                                        Some(program) => {
+                                            if let Some(corrected) = external::run("command ", program, &parts[2..], &session.env, &self.config.correction) {
+                                                queued_commands.push_front(corrected);
+                                            }
+                                        },
+                                        None => print_error("Usage: command <name> [args]..."),
+                                    },
+                                    _ => {
+                                        if let Some(corrected) = external::run("", cmd, &parts[1..], &session.env, &self.config.correction) {
+                                            queued_commands.push_front(corrected);
+                                        }
+                                    },
+                                }
+                            }
+                            if timed && !parts.is_empty() {
+                                if let Err(e) = timer.report(&mut io::stdout()) {
+                                    print_error(e);
                                 }
+                            } else {
+                                timer.finish(input, &self.config.timing);
                             }
+                            history::record(input, session_name, &cwd);
 
                             if let Some(home) = home_dir() {
                                 let history_path = home.join(".partermai_history");
                                 editor.save_history(&history_path).unwrap_or_default();
                             }
+                            if next.is_some() {
+                                break;
+                            }
                         },
                         Err(_) => {
                             println!("{}", "Error reading input".red());
@@ -328,56 +874,289 @@ impl SessionManager {
             }
         } else {
             println!("{}", "No active session. Create one using 'partermai new <name>'".red());
+            return false;
+        }
+        self.apply(next.unwrap_or(SessionAction::End))
+    }
+
+    // Carries out what a `partermai` command asked of the session manager
+    fn apply(&mut self, action: SessionAction) -> bool {
+        match action {
+            SessionAction::Stay => {},
+            SessionAction::New { name, profile } => {
+                let created = match profile {
+                    Some(profile) => self.create_session_from_profile(&name, &profile),
+                    None => self.create_session(&name),
+                };
+                if created {
+                    self.switch_session(&name);
+                }
+            },
+            SessionAction::Switch(name) => self.switch_session(&name),
+            SessionAction::List => self.list_sessions(),
+            SessionAction::Close(name) => self.close_session(&name),
+            SessionAction::Export { name, path } => {
+                let result = match self.sessions.get(&name) {
+                    Some(session) => bundle::export(session, &path, &mut io::stdout()),
+                    None => Err(io::Error::new(io::ErrorKind::NotFound, format!("No session named {}", name))),
+                };
+                if let Err(e) = result {
+                    print_error(e);
+                }
+            },
+            // A bundle becomes a session of its own, renamed if its name is taken here
+            SessionAction::Import(path) => {
+                let result = bundle::read(&path).and_then(|bundle| {
+                    let name = self.unused_name(&bundle.name);
+                    bundle::restore(bundle, name, &mut io::stdout())
+                });
+                match result {
+                    Ok(session) => {
+                        let name = session.name.clone();
+                        self.sessions.insert(name.clone(), session);
+                        self.switch_session(&name);
+                    },
+                    Err(e) => print_error(e),
+                }
+            },
+            // Every pane is a new Partermai process starting in this directory, so a name
+            // that is already open here would only give a blank session of the same name
+            SessionAction::Mux(names) => {
+                let result = match names.iter().find(|name| self.sessions.contains_key(*name)) {
+                    Some(name) => Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                        format!("Session {} is already open here; mux panes start new sessions, so pick another name", name))),
+                    #[cfg(unix)]
+                    None => match self.active_session.as_ref().and_then(|name| self.sessions.get(name)) {
+                        Some(current) => mux::run(&names, current),
+                        None => Ok(()),
+                    },
+                    #[cfg(not(unix))]
+                    None => Err(io::Error::other("The multiplexer needs a Unix terminal")),
+                };
+                if let Err(e) = result {
+                    print_error(e);
+                }
+            },
+            // An open session moves into the daemon and is gone from here afterwards
+            SessionAction::Attach { name, profile } => {
+                #[cfg(unix)]
+                let result = match self.active_session.as_ref().and_then(|active| self.sessions.get(active)) {
+                    Some(current) => daemon::attach_session(&name, profile.as_deref(), self.sessions.get(&name), &current.env, &mut io::stdout()),
+                    None => Ok(false),
+                };
+                #[cfg(not(unix))]
+                let result: io::Result<bool> = {
+                    let _ = profile;
+                    Err(io::Error::other(format!("Can't run {} in a daemon: detachable sessions need Unix domain sockets", name)))
+                };
+                match result {
+                    Ok(true) if self.active_session.as_deref() == Some(name.as_str()) => return self.apply(SessionAction::End),
+                    Ok(true) => {
+                        self.sessions.remove(&name);
+                    },
+                    Ok(false) => {},
+                    Err(e) => print_error(e),
+                }
+            },
+            // The session itself ended; carry on with another one if there is any
+            SessionAction::End => {
+                let Some(session) = self.active_session.take().and_then(|name| self.sessions.remove(&name)) else { return false };
+                match self.sessions.keys().min().cloned() {
+                    Some(name) => {
+                        println!("{}: {}", "Closed session".green(), session.name);
+                        self.switch_session(&name);
+                    },
+                    None => {
+                        session.print_goodbye();
+                        return false;
+                    },
+                }
+            },
         }
+        true
     }
 }
 
-// Function to handle the `partermai` built-in command
-fn handle_partermcli(input: &str, _session: &mut Session) {
-    let parts: Vec<&str> = input.split_whitespace().collect();
-    match parts.get(1).map(|s| *s) {
+// Function to handle the `partermai` built-in command. Anything that involves other
+// sessions is handed back to the session manager.
+fn handle_partermcli(parts: &[&str], session: &mut Session, config: &Config) -> SessionAction {
+    let mut stdout = io::stdout();
+    match parts.get(1).copied() {
+        Some("new") => match parts[2..] {
+            [name] => return SessionAction::New { name: name.to_string(), profile: None },
+            [name, "--profile", profile] => return SessionAction::New { name: name.to_string(), profile: Some(profile.to_string()) },
+            _ => print_error("Usage: partermai new <name> [--profile <profile>]"),
+        },
+        Some("switch") => match parts[2..] {
+            [name] if name == session.name => println!("Already in session {}", name.yellow()),
+            [name] => return SessionAction::Switch(name.to_string()),
+            _ => print_error("Usage: partermai switch <name>"),
+        },
+        Some("list") => return SessionAction::List,
+        Some("close") => match parts[2..] {
+            [name] => return SessionAction::Close(name.to_string()),
+            _ => print_error("Usage: partermai close <name>"),
+        },
+        Some("export") => match parts[2..] {
+            [file] => {
+                if let Err(e) = bundle::export(session, &session.env.resolve_path(file), &mut stdout) {
+                    print_error(e);
+                }
+            },
+            // The file is relative to where the command was typed, whichever session it saves
+            [name, file] => return SessionAction::Export { name: name.to_string(), path: session.env.resolve_path(file) },
+            _ => print_error("Usage: partermai export [session] <file>"),
+        },
+        Some("import") => match parts[2..] {
+            [file] => return SessionAction::Import(session.env.resolve_path(file)),
+            _ => print_error("Usage: partermai import <file>"),
+        },
+        Some("mux") => return SessionAction::Mux(parts[2..].iter().map(|name| name.to_string()).collect()),
+        Some("record" | "replay") => {
+            if let Err(e) = cast::execute_cast_command(&parts[1..], &session.name, &session.env, &mut stdout) {
+                print_error(e);
+            }
+        },
+        Some("attach") if parts.len() > 2 => match parts[2..] {
+            [name] => return SessionAction::Attach { name: name.to_string(), profile: None },
+            [name, "--profile", profile] => return SessionAction::Attach { name: name.to_string(), profile: Some(profile.to_string()) },
+            _ => print_error("Usage: partermai attach [name [--profile <profile>]]"),
+        },
+        Some("daemon" | "attach" | "detach") => {
+            #[cfg(unix)]
+            if let Err(e) = daemon::execute_daemon_command(&parts[1..], &mut stdout) {
+                print_error(e);
+            }
+            #[cfg(not(unix))]
+            print_error("Detachable sessions need Unix domain sockets");
+        },
         Some("help") => {
-            println!("\n🌟 Welcome to Partermai CLI Help 🌟\n");
-            
-            println!("Session Management:");
-            println!("  {} - Create a new session", "partermai new <name>".yellow());
-            println!("  {} - Switch to a session", "partermai switch <name>".yellow());
-            println!("  {} - List all sessions", "partermai list".yellow());
-            println!("  {} - Close a session", "partermai close <name>".yellow());
-            
-            println!("\nFile Operations:");
-            println!("  {} - List directory contents", "ls [-a] [path]".yellow());
-            println!("    -a: Show hidden files");
-            println!("  {} - Change directory", "cd [path]".yellow());
-            println!("    ~: Home directory");
-            println!("    ..: Parent directory");
-            println!("  {} - Print working directory", "pwd".yellow());
-            println!("  {} - Display file contents", "cat <file>".yellow());
-            
-            println!("\nUtilities:");
-            println!("  {} - Show command history", "history".yellow());
-            println!("  {} - Clear the screen", "clear".yellow());
-            println!("  {} - Show a random tip", "tip".yellow());
-            println!("  {} - Show this help message", "partermai help".yellow());
-            println!("  {} - Exit the current session", "exit/quit".yellow());
-            
-            println!("\nVoia AI Assistant:");
-            println!("  {} - Start Voia and ask a question", "voia <question>".yellow());
-            println!("  {} - Set your OpenAI API key", "voia-setkey <key>".yellow());
-            println!("  {} - Change AI model", "voia-model [model]".yellow());
-            println!("  {} - Clear conversation history", "voia-clear".yellow());
-            println!("Available models: gpt-3.5-turbo, gpt-4, text-davinci-003");
-            println!("Note: Requires OpenAI API key in .env file");
-            
-            println!("\nTips:");
-            println!("- Use Tab for command completion");
-            println!("- Press Ctrl+R to search through command history");
-            println!("- Commands are case-sensitive");
-            println!("- Use ~ to refer to your home directory");
-            println!("- Colors indicate file types in ls output");
+            let mut pager = Pager::new(&config.pager);
+            if let Err(e) = print_help(&mut pager).and_then(|_| pager.finish()) {
+                print_error(e);
+            }
         },
         _ => println!("{}", "Unknown command. Try 'partermai help'".red()),
     }
+    SessionAction::Stay
+}
+
+// Writes the `partermai help` text
+fn print_help(out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "\n🌟 Welcome to Partermai CLI Help 🌟\n")?;
+
+    writeln!(out, "Session Management:")?;
+    writeln!(out, "  {} - Create a new session and switch to it", "partermai new <name>".yellow())?;
+    writeln!(out, "  {} - Start it from a profile in the config", "partermai new <name> --profile <profile>".yellow())?;
+    writeln!(out, "  {} - Switch to a session", "partermai switch <name>".yellow())?;
+    writeln!(out, "  {} - List all sessions", "partermai list".yellow())?;
+    writeln!(out, "  {} - Close a session", "partermai close <name>".yellow())?;
+    writeln!(out, "  {} - Save a session, this one by default (directories, env vars, aliases, history, Voia), as JSON", "partermai export [name] <file>".yellow())?;
+    writeln!(out, "  {} - Open a saved session as a new session and switch to it", "partermai import <file>".yellow())?;
+    writeln!(out, "  {} - Split the screen into panes, each a new session in this directory (names must not be open already)", "partermai mux [name]...".yellow())?;
+    writeln!(out, "    Ctrl+B then: % split, \" stack, arrows or hjkl move focus, HJKL resize,")?;
+    writeln!(out, "    o next pane, z zoom, x close pane, d leave, ? list keys, Ctrl+B send Ctrl+B")?;
+    writeln!(out, "  {} - Start the background daemon that keeps detached sessions running", "partermai daemon [stop]".yellow())?;
+    writeln!(out, "  {} - Attach to a daemon session, creating it (from a profile, if given) as needed; a session open here moves into the daemon; without a name, list them", "partermai attach [name [--profile <profile>]]".yellow())?;
+    writeln!(out, "  {} - Detach from the daemon session (or press Ctrl+B then d)", "partermai detach".yellow())?;
+    writeln!(out, "  {} - Record the session to an asciicast v2 file until 'partermai record stop'; --stdin keeps keystrokes too", "partermai record start [--stdin] [file]".yellow())?;
+    writeln!(out, "  {} - Play a recording back; in playback, space pauses, +/- change the speed, q stops", "partermai replay <file> [-s speed]".yellow())?;
+
+    writeln!(out, "\nFile Operations:")?;
+    writeln!(out, "  {} - List directory contents", "ls [-a1C] [--git] [path]".yellow())?;
+    writeln!(out, "    -a: Show hidden files")?;
+    writeln!(out, "    -1: One entry per line")?;
+    writeln!(out, "    -C: Arrange entries in columns")?;
+    writeln!(out, "    --git: Show git status as reported by `git status` (staged/worktree, ?? untracked, !! ignored)")?;
+    writeln!(out, "  {} - Show a directory tree (honors .gitignore)", "tree [-a] [-d] [-L depth] [path]".yellow())?;
+    writeln!(out, "    -a: Show hidden files")?;
+    writeln!(out, "    -d: Directories only")?;
+    writeln!(out, "    -L: Limit the depth")?;
+    writeln!(out, "  {} - Change directory", "cd [-L|-P] [path]".yellow())?;
+    writeln!(out, "    -P: Resolve symlinks; -L (default) keeps the path as typed")?;
+    writeln!(out, "    Relative paths are also looked up in $CDPATH")?;
+    writeln!(out, "    ~: Home directory")?;
+    writeln!(out, "    ..: Parent directory")?;
+    writeln!(out, "    -: Previous directory")?;
+    writeln!(out, "  {} - Jump to a frequently used directory matching the keywords", "z/j [-i] [-l] <keywords>".yellow())?;
+    writeln!(out, "    -i: Pick from the matches interactively")?;
+    writeln!(out, "    -l: List known directories by score")?;
+    writeln!(out, "  {} - Bookmark the current (or given) directory", "mark <name> [dir]".yellow())?;
+    writeln!(out, "  {} - Change to a bookmarked directory", "jump <name>".yellow())?;
+    writeln!(out, "  {} - List bookmarks", "marks".yellow())?;
+    writeln!(out, "  {} - Remove a bookmark", "unmark <name>".yellow())?;
+    writeln!(out, "    Bookmarks also work in paths: {}", "cat @work/src/main.rs".yellow())?;
+    writeln!(out, "  {} - Push a directory onto the stack and change to it", "pushd [dir|+N]".yellow())?;
+    writeln!(out, "    No argument swaps the top two entries, +N rotates the Nth entry to the top")?;
+    writeln!(out, "  {} - Pop the top of the directory stack and change to it", "popd [+N]".yellow())?;
+    writeln!(out, "  {} - Show the directory stack", "dirs [-v] [-c]".yellow())?;
+    writeln!(out, "    -v: One entry per line, numbered")?;
+    writeln!(out, "    -c: Clear the stack")?;
+    writeln!(out, "  {} - Print working directory", "pwd".yellow())?;
+    writeln!(out, "  {} - Run a command and report real, user and sys time and peak memory", "time <command>".yellow())?;
+    writeln!(out, "  {} - Tell whether a name runs a builtin or a program on $PATH", "type <name>...".yellow())?;
+    writeln!(out, "  {} - Show what a name runs; -a lists every match", "which [-a] <name>...".yellow())?;
+    writeln!(out, "  {} - Run the program on $PATH even if a builtin has that name", "command <name> [args]".yellow())?;
+    writeln!(out, "  {} - Display file contents with syntax highlighting", "cat [-n] [-p] [-x] <file>...".yellow())?;
+    writeln!(out, "    -n: Number output lines")?;
+    writeln!(out, "    -p: Plain output, no highlighting")?;
+    writeln!(out, "    -x: Hexdump, for binary files")?;
+    writeln!(out, "  {} - Find files, skipping hidden and .gitignore'd ones", "find [path]... [filters]".yellow())?;
+    writeln!(out, "    -name/-iname <glob>, -regex <re>, -type f|d|l, -maxdepth N")?;
+    writeln!(out, "    -size [+|-]N[k|M|G], -mtime [+|-]N (days), -hidden, -no-ignore")?;
+    writeln!(out, "  {} - Fuzzy-find a file and put its path on the input line (also Ctrl+T)", "ff [command]".yellow())?;
+    writeln!(out, "  {} - Search for a regex in files", "grep [-ivnclrwF] <pattern> [file]...".yellow())?;
+    writeln!(out, "    -i: Ignore case   -v: Non-matching lines   -n: Line numbers   -c: Count matches")?;
+    writeln!(out, "    -l: File names only   -w: Whole words   -F: Fixed string")?;
+    writeln!(out, "    -r: Search directories, skipping hidden and .gitignore'd files")?;
+    writeln!(out, "  {} - Show the first lines of files", "head [-n N] [file]...".yellow())?;
+    writeln!(out, "  {} - Show the last lines of files", "tail [-n N] [-f] [file]...".yellow())?;
+    writeln!(out, "    -f: Keep printing what is appended (q or Ctrl+C to stop)")?;
+    writeln!(out, "  {} - Count lines, words and bytes", "wc [-lwc] [file]...".yellow())?;
+    writeln!(out, "  {} - Sort lines", "sort [-rnuf] [file]...".yellow())?;
+    writeln!(out, "    -r: Reverse   -n: Numeric   -u: Unique   -f: Ignore case")?;
+    writeln!(out, "  {} - Merge adjacent duplicate lines", "uniq [-cdui] [file]...".yellow())?;
+    writeln!(out, "    -c: Prefix counts   -d: Only duplicates   -u: Only unique lines   -i: Ignore case")?;
+    writeln!(out, "    Without files, these read standard input (Ctrl+D to finish)")?;
+    writeln!(out, "  {} - Create directories", "mkdir [-p] <dir>...".yellow())?;
+    writeln!(out, "    -p: Create missing parents, no error if it exists")?;
+    writeln!(out, "  {} - Create files or update their timestamps", "touch <file>...".yellow())?;
+    writeln!(out, "  {} - Copy files", "cp [-r] <source>... <dest>".yellow())?;
+    writeln!(out, "    -r: Copy directories recursively")?;
+    writeln!(out, "  {} - Move or rename files", "mv <source>... <dest>".yellow())?;
+    writeln!(out, "    cp and mv move anything they would overwrite to the trash")?;
+    writeln!(out, "  {} - Move files to the trash", "rm [-r] [-f] [--permanent] <path>...".yellow())?;
+    writeln!(out, "    -r: Remove directories and their contents")?;
+    writeln!(out, "    -f: Ignore missing files")?;
+    writeln!(out, "    --permanent: Delete instead of trashing (can't be undone)")?;
+    writeln!(out, "  {} - Manage the trash", "trash [list|restore <item>|empty]".yellow())?;
+    writeln!(out, "  {} - Reverse the last rm, mv or cp in this session", "undo".yellow())?;
+
+    writeln!(out, "\nUtilities:")?;
+    writeln!(out, "  {} - Show command history", "history".yellow())?;
+    writeln!(out, "  {} - Clear the screen", "clear".yellow())?;
+    writeln!(out, "  {} - Show a random tip", "tip".yellow())?;
+    writeln!(out, "  {} - Show this help message", "partermai help".yellow())?;
+    writeln!(out, "  {} - Exit the current session", "exit/quit".yellow())?;
+
+    writeln!(out, "\nVoia AI Assistant:")?;
+    writeln!(out, "  {} - Start Voia and ask a question", "voia <question>".yellow())?;
+    writeln!(out, "  {} - Set your OpenAI API key", "voia-setkey <key>".yellow())?;
+    writeln!(out, "  {} - Change AI model", "voia-model [model]".yellow())?;
+    writeln!(out, "  {} - Clear conversation history", "voia-clear".yellow())?;
+    writeln!(out, "Available models: gpt-3.5-turbo, gpt-4, text-davinci-003")?;
+    writeln!(out, "Note: Requires OpenAI API key in .env file")?;
+
+    writeln!(out, "\nTips:")?;
+    writeln!(out, "- Use Tab for command completion")?;
+    writeln!(out, "- Press Ctrl+R to fuzzy-search command history; in the picker,")?;
+    writeln!(out, "  Ctrl+S limits it to this session, Ctrl+D to this directory, Ctrl+E to succeeded/failed")?;
+    writeln!(out, "- Long output opens in a pager: arrows/space to scroll, / to search, n/N for next/previous, q to quit")?;
+    writeln!(out, "- Commands are case-sensitive")?;
+    writeln!(out, "- Use ~ to refer to your home directory")?;
+    writeln!(out, "- Colors indicate file types in ls output (honors LS_COLORS)")?;
+    writeln!(out, "- Set \"ls\": {{ \"icons\": true }} in the config file for Nerd Font icons")?;
+    Ok(())
 }
 
 #[derive(Serialize)]
@@ -402,10 +1181,11 @@ struct Voia {
     api_key: String,
     model: String,
     conversation_history: Vec<String>,
+    persona: Option<String>, // Put before every conversation, e.g. from a session profile
 }
 
 impl Voia {
-    fn new() -> Self {
+    fn new(persona: Option<String>) -> Self {
         dotenv().ok();
         let api_key = std::env::var("OPENAI_API_KEY")
             .expect("OPENAI_API_KEY must be set in environment");
@@ -415,6 +1195,7 @@ impl Voia {
             api_key,
             model: String::from("gpt-3.5-turbo"),
             conversation_history: Vec::new(),
+            persona,
         }
     }
 
@@ -436,7 +1217,7 @@ impl Voia {
     async fn ask(&mut self, question: &str) -> Result<String, Box<dyn std::error::Error>> {
         self.conversation_history.push(format!("User: {}", question));
         
-        let context = self.conversation_history.join("\n");
+        let context = self.persona.iter().chain(&self.conversation_history).cloned().collect::<Vec<_>>().join("\n");
         let request = VoiaRequest {
             prompt: format!("{}\nVoia:", context),
             max_tokens: 150,
@@ -469,86 +1250,10 @@ impl Voia {
     }
 }
 
-fn execute_ls(path: Option<&str>, env: &Environment, show_hidden: bool) -> io::Result<()> {
-    let target_path = path.map_or_else(|| env.current_dir.clone(), |p| {
-        if p == "~" {
-            env.home_dir.clone()
-        } else if p.starts_with("~/") {
-            env.home_dir.join(&p[2..])
-        } else {
-            Path::new(p).to_path_buf()
-        }
-    });
-
-    let entries = fs::read_dir(&target_path)?;
-    let mut files: Vec<_> = entries
-        .filter_map(Result::ok)
-        .filter(|entry| {
-            let name = entry.file_name();
-            let name_str = name.to_string_lossy();
-            show_hidden || !name_str.starts_with('.')
-        })
-        .collect();
-
-    files.sort_by_key(|entry| entry.file_name());
-
-    for entry in files {
-        let metadata = entry.metadata()?;
-        let name = entry.file_name();
-        let name_str = name.to_string_lossy();
-
-        if metadata.is_dir() {
-            print!("{} ", name_str.blue().bold());
-        } else {
-            print!("{} ", name_str);
-        }
-    }
-    println!();
-    Ok(())
-}
-
 fn execute_pwd(env: &Environment) {
     println!("{}", env.get_current_dir_display());
 }
 
-fn execute_cat(path: &str, env: &Environment) -> io::Result<()> {
-    let target_path = if path == "~" {
-        env.home_dir.clone()
-    } else if path.starts_with("~/") {
-        env.home_dir.join(&path[2..])
-    } else {
-        env.current_dir.join(path)
-    };
-
-    let content = fs::read_to_string(&target_path)?;
-    print!("{}", content);
-    Ok(())
-}
-
-// Simple text editor function
-fn start_text_editor() -> io::Result<()> {
-    let mut content = String::new();
-    println!("Simple text editor (Press Ctrl+D or type 'exit' to save and exit)");
-    
-    loop {
-        let mut line = String::new();
-        if io::stdin().read_line(&mut line)? == 0 || line.trim() == "exit" {
-            break;
-        }
-        content.push_str(&line);
-    }
-    
-    println!("Enter filename to save:");
-    let mut filename = String::new();
-    io::stdin().read_line(&mut filename)?;
-    let filename = filename.trim();
-    
-    let mut file = File::create(filename)?;
-    file.write_all(content.as_bytes())?;
-    println!("{}: {}", "File saved".green(), filename);
-    Ok(())
-}
-
 fn print_welcome_banner() {
     let banner = r#"
     ╔═══════════════════════════════════════╗
@@ -562,9 +1267,33 @@ fn print_welcome_banner() {
     println!("{} Started at: {}\n", "🕒".bright_yellow(), now.format("%Y-%m-%d %H:%M:%S").to_string().bright_green());
 }
 
+// `Partermai --session <name> [--profile <profile>]` starts straight into that session, as
+// the multiplexer's panes do
+fn arg_value(flag: &str) -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == flag {
+            return args.next();
+        }
+    }
+    None
+}
+
 #[tokio::main]
 async fn main() {
-    print_welcome_banner();
+    // The background daemon started by `partermai daemon` or `partermai attach`
+    #[cfg(unix)]
+    if env::args().any(|arg| arg == "--daemon") {
+        if let Err(e) = daemon::serve() {
+            print_error(e);
+        }
+        return;
+    }
+
+    let session_name = arg_value("--session");
+    if session_name.is_none() {
+        print_welcome_banner();
+    }
 
     // Load syntax sets and themes
     let ps = SyntaxSet::load_defaults_newlines();
@@ -573,8 +1302,47 @@ async fn main() {
     let mut session_manager = SessionManager::new();
     
     // Create a default session
-    session_manager.create_session("main");
-    session_manager.switch_session("main");
-    
-    session_manager.run_active_session(&ps, &ts).await;
+    let session_name = session_name.unwrap_or_else(|| "main".to_string());
+    let profile = arg_value("--profile");
+    let created = match (arg_value("--bundle"), &profile) {
+        (Some(bundle), _) => session_manager.create_session_from_bundle(&session_name, Path::new(&bundle), profile.clone()),
+        (None, Some(profile)) => session_manager.create_session_from_profile(&session_name, profile),
+        (None, None) => false,
+    };
+    if !created {
+        session_manager.create_session(&session_name);
+    }
+    session_manager.switch_session(&session_name);
+
+    // Show a random tip at start
+    println!("{}", Session::get_random_tip().bright_yellow());
+
+    while session_manager.run_active_session(&ps, &ts).await {}
+    // Leave the terminal as it was found, even when the last session ends mid-recording
+    if let Err(e) = cast::stop_recording() {
+        print_error(e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_path_resolves_dots_lexically() {
+        assert_eq!(normalize_path(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize_path(Path::new("/a/b/../../..")), PathBuf::from("/"));
+        assert_eq!(normalize_path(Path::new("/a/link/..")), PathBuf::from("/a"));
+        assert_eq!(normalize_path(Path::new("/a/b/")), PathBuf::from("/a/b"));
+    }
+
+    #[test]
+    fn resolve_path_leaves_parent_dirs_to_the_kernel() {
+        let mut env = Environment::new();
+        env.current_dir = PathBuf::from("/a/link");
+        env.home_dir = PathBuf::from("/home/me");
+        assert_eq!(env.resolve_path("../x"), PathBuf::from("/a/link/../x"));
+        assert_eq!(env.resolve_path("~/y"), PathBuf::from("/home/me/y"));
+        assert_eq!(env.resolve_path("/abs"), PathBuf::from("/abs"));
+    }
 }
@@ -0,0 +1,642 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::process::Command;
+use colored::*;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use unicode_width::UnicodeWidthStr;
+use crate::pty::{self, PtyChild};
+use crate::timing::try_wait_child;
+
+// Ctrl+B, as in tmux; pressing it twice sends one to the pane
+const PREFIX: u8 = 0x02;
+// Set in every pane, so `partermai mux` inside one doesn't nest a multiplexer
+pub const NESTED_VAR: &str = "PARTERMAI_MUX";
+const POLL_INTERVAL_MS: i32 = 50;
+const BUFFER_SIZE: usize = 8192;
+const RESIZE_STEP: f32 = 0.05;
+const KEYS_HELP: &str = "% split  \" stack  arrows/hjkl focus  HJKL resize  o next  z zoom  x close  d quit";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Orientation {
+    SideBySide,
+    Stacked,
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+struct Rect {
+    x: u16,
+    y: u16,
+    width: u16,
+    height: u16,
+}
+
+impl Rect {
+    // The two halves of a split, and the one-cell line between them
+    fn split(self, orientation: Orientation, ratio: f32) -> (Rect, Rect, Rect) {
+        let total = if orientation == Orientation::SideBySide { self.width } else { self.height };
+        let usable = total.saturating_sub(1);
+        let first = if usable < 2 { usable } else { ((usable as f32 * ratio).round() as u16).clamp(1, usable - 1) };
+        let second = usable - first;
+        match orientation {
+            Orientation::SideBySide => (
+                Rect { width: first, ..self },
+                Rect { x: self.x + first + 1, width: second, ..self },
+                Rect { x: self.x + first, width: 1.min(total), ..self },
+            ),
+            Orientation::Stacked => (
+                Rect { height: first, ..self },
+                Rect { y: self.y + first + 1, height: second, ..self },
+                Rect { y: self.y + first, height: 1.min(total), ..self },
+            ),
+        }
+    }
+
+    fn overlaps_rows(&self, other: &Rect) -> bool {
+        self.y < other.y + other.height && other.y < self.y + self.height
+    }
+
+    fn overlaps_columns(&self, other: &Rect) -> bool {
+        self.x < other.x + other.width && other.x < self.x + self.width
+    }
+}
+
+// How the screen is divided: each split gives `ratio` of its space to `first`
+enum Layout {
+    Pane(usize),
+    Split {
+        orientation: Orientation,
+        ratio: f32,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    fn contains(&self, id: usize) -> bool {
+        match self {
+            Layout::Pane(pane) => *pane == id,
+            Layout::Split { first, second, .. } => first.contains(id) || second.contains(id),
+        }
+    }
+
+    fn arrange(&self, area: Rect, panes: &mut Vec<(usize, Rect)>, borders: &mut Vec<(Orientation, Rect)>) {
+        match self {
+            Layout::Pane(id) => panes.push((*id, area)),
+            Layout::Split { orientation, ratio, first, second } => {
+                let (first_area, second_area, border) = area.split(*orientation, *ratio);
+                first.arrange(first_area, panes, borders);
+                second.arrange(second_area, panes, borders);
+                borders.push((*orientation, border));
+            }
+        }
+    }
+
+    // Puts `new` next to `target`, which keeps the first half
+    fn split(&mut self, target: usize, new: usize, orientation: Orientation) {
+        match self {
+            Layout::Pane(id) if *id == target => {
+                *self = Layout::Split {
+                    orientation,
+                    ratio: 0.5,
+                    first: Box::new(Layout::Pane(target)),
+                    second: Box::new(Layout::Pane(new)),
+                };
+            }
+            Layout::Pane(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(target, new, orientation);
+                second.split(target, new, orientation);
+            }
+        }
+    }
+
+    // The layout without `target`; its sibling takes over the space
+    fn remove(self, target: usize) -> Option<Layout> {
+        match self {
+            Layout::Pane(id) if id == target => None,
+            Layout::Pane(_) => Some(self),
+            Layout::Split { orientation, ratio, first, second } => match (first.remove(target), second.remove(target)) {
+                (Some(first), Some(second)) => Some(Layout::Split {
+                    orientation,
+                    ratio,
+                    first: Box::new(first),
+                    second: Box::new(second),
+                }),
+                (Some(only), None) | (None, Some(only)) => Some(only),
+                (None, None) => None,
+            },
+        }
+    }
+
+    // Grows (or with a negative `delta`, shrinks) `target` by moving the nearest border
+    // of the given orientation
+    fn resize(&mut self, target: usize, orientation: Orientation, delta: f32) -> bool {
+        let Layout::Split { orientation: split, ratio, first, second } = self else { return false };
+        let in_first = first.contains(target);
+        if !in_first && !second.contains(target) {
+            return false;
+        }
+        let inner = if in_first { first.resize(target, orientation, delta) } else { second.resize(target, orientation, delta) };
+        if inner || *split != orientation {
+            return inner;
+        }
+        *ratio = (*ratio + if in_first { delta } else { -delta }).clamp(0.1, 0.9);
+        true
+    }
+}
+
+// Answers the questions programs ask their terminal, which a real terminal would answer
+// for us, and collects bells and notifications to pass on
+#[derive(Default)]
+pub struct Replies {
+    pub to_program: Vec<u8>,
+    pub to_terminal: Vec<u8>,
+}
+
+impl vt100::Callbacks for Replies {
+    fn audible_bell(&mut self, _: &mut vt100::Screen) {
+        self.to_terminal.push(0x07);
+    }
+
+    fn unhandled_csi(&mut self, screen: &mut vt100::Screen, i1: Option<u8>, _: Option<u8>, params: &[&[u16]], c: char) {
+        match (i1, params, c) {
+            // Cursor position report, which line editors use to find the start of the line
+            (None, [[6]], 'n') => {
+                let (row, col) = screen.cursor_position();
+                self.to_program.extend(format!("\x1B[{};{}R", row + 1, col + 1).bytes());
+            }
+            (None, [[5]], 'n') => self.to_program.extend(b"\x1B[0n"),
+            // Device attributes: a VT220 with nothing special
+            (None, [] | [[0]], 'c') => self.to_program.extend(b"\x1B[?62c"),
+            _ => {}
+        }
+    }
+
+    // Desktop notifications (OSC 9), such as the ones for long-running commands
+    fn unhandled_osc(&mut self, _: &mut vt100::Screen, params: &[&[u8]]) {
+        if params.first() == Some(&&b"9"[..]) {
+            self.to_terminal.extend(b"\x1B]");
+            self.to_terminal.extend(params.join(&b';'));
+            self.to_terminal.push(0x07);
+        }
+    }
+}
+
+// Another Partermai process that goes straight into `session`. It isn't in a pane just
+// because this process is; the multiplexer sets the variable again for its own panes.
+pub fn session_command(session: &str, profile: Option<&str>, cwd: &Path) -> io::Result<Command> {
+    let mut command = Command::new(env::current_exe()?);
+    command.args(["--session", session])
+        .current_dir(cwd)
+        .env_remove(NESTED_VAR);
+    if let Some(profile) = profile {
+        command.args(["--profile", profile]);
+    }
+    Ok(command)
+}
+
+struct Pane {
+    session: String,
+    pty: PtyChild,
+    screen: vt100::Parser<Replies>,
+    rect: Rect,
+}
+
+impl Pane {
+    fn set_rect(&mut self, rect: Rect) {
+        if (rect.width, rect.height) != (self.rect.width, self.rect.height) {
+            self.screen.screen_mut().set_size(rect.height.max(1), rect.width.max(1));
+            self.pty.resize(rect.height.max(1), rect.width.max(1));
+        }
+        self.rect = rect;
+    }
+
+    fn draw(&self, out: &mut Vec<u8>) -> io::Result<()> {
+        let Rect { x, y, width, height } = self.rect;
+        let blank = " ".repeat(width as usize);
+        for (i, row) in self.screen.screen().rows_formatted(0, width).enumerate().take(height as usize) {
+            queue!(out, MoveTo(x, y + i as u16))?;
+            write!(out, "\x1B[0m{}", blank)?;
+            queue!(out, MoveTo(x, y + i as u16))?;
+            out.extend(row);
+        }
+        write!(out, "\x1B[0m")
+    }
+}
+
+struct Mux<'a> {
+    cwd: &'a Path,
+    profiles: BTreeMap<String, String>, // Sessions to start from a config profile, by name
+    panes: BTreeMap<usize, Pane>,
+    layout: Option<Layout>,
+    borders: Vec<(Orientation, Rect)>,
+    visible: Vec<usize>,
+    focus: usize,
+    next_id: usize,
+    zoomed: bool,
+    prefix: bool,    // Ctrl+B was pressed and the next key is a command
+    show_keys: bool, // The status bar lists the key bindings
+    size: (u16, u16),
+}
+
+impl Mux<'_> {
+    fn open_pane(&mut self, session: String) -> io::Result<usize> {
+        let mut command = session_command(&session, self.profiles.get(&session).map(String::as_str), self.cwd)?;
+        command.env(NESTED_VAR, "1").env("TERM", "screen-256color");
+        let (cols, rows) = self.size;
+        let pty = pty::spawn_sized(command, rows.saturating_sub(1).max(1), cols)?;
+        pty.set_nonblocking()?;
+        let id = self.next_id;
+        self.next_id += 1;
+        let pane = Pane {
+            session,
+            pty,
+            screen: vt100::Parser::new_with_callbacks(rows.saturating_sub(1).max(1), cols, 0, Replies::default()),
+            rect: Rect { width: cols, height: rows.saturating_sub(1).max(1), ..Rect::default() },
+        };
+        self.panes.insert(id, pane);
+        Ok(id)
+    }
+
+    // New panes get sessions named after them, skipping names already on screen
+    fn new_session_name(&self) -> String {
+        (self.next_id + 1..)
+            .map(|n| format!("pane-{}", n))
+            .find(|name| !self.panes.values().any(|pane| pane.session == *name))
+            .unwrap_or_default()
+    }
+
+    fn split(&mut self, orientation: Orientation) -> io::Result<()> {
+        let id = self.open_pane(self.new_session_name())?;
+        match &mut self.layout {
+            Some(layout) => layout.split(self.focus, id, orientation),
+            None => self.layout = Some(Layout::Pane(id)),
+        }
+        self.focus = id;
+        self.zoomed = false;
+        self.relayout();
+        Ok(())
+    }
+
+    fn close(&mut self, id: usize) {
+        if let Some(mut pane) = self.panes.remove(&id) {
+            pane.pty.kill();
+        }
+        self.layout = self.layout.take().and_then(|layout| layout.remove(id));
+        if self.focus == id {
+            self.focus = self.panes.keys().next().copied().unwrap_or(0);
+            self.zoomed = false;
+        }
+        self.relayout();
+    }
+
+    fn relayout(&mut self) {
+        let (cols, rows) = self.size;
+        let area = Rect { x: 0, y: 0, width: cols, height: rows.saturating_sub(1).max(1) };
+        let mut rects = Vec::new();
+        self.borders.clear();
+        if self.zoomed {
+            rects.push((self.focus, area));
+        } else if let Some(layout) = &self.layout {
+            layout.arrange(area, &mut rects, &mut self.borders);
+        }
+        self.visible = rects.iter().map(|(id, _)| *id).collect();
+        for (id, rect) in rects {
+            if let Some(pane) = self.panes.get_mut(&id) {
+                pane.set_rect(rect);
+            }
+        }
+    }
+
+    // The nearest visible pane in `direction` that lines up with the focused one
+    fn neighbour(&self, direction: Direction) -> Option<usize> {
+        let current = self.panes.get(&self.focus)?.rect;
+        self.visible.iter()
+            .filter(|id| **id != self.focus)
+            .filter_map(|id| {
+                let rect = self.panes.get(id)?.rect;
+                let distance = match direction {
+                    Direction::Left if rect.overlaps_rows(&current) => current.x.checked_sub(rect.x + rect.width)?,
+                    Direction::Right if rect.overlaps_rows(&current) => rect.x.checked_sub(current.x + current.width)?,
+                    Direction::Up if rect.overlaps_columns(&current) => current.y.checked_sub(rect.y + rect.height)?,
+                    Direction::Down if rect.overlaps_columns(&current) => rect.y.checked_sub(current.y + current.height)?,
+                    _ => return None,
+                };
+                let offset = match direction {
+                    Direction::Left | Direction::Right => rect.y.abs_diff(current.y),
+                    Direction::Up | Direction::Down => rect.x.abs_diff(current.x),
+                };
+                Some(((distance, offset), *id))
+            })
+            .min()
+            .map(|(_, id)| id)
+    }
+
+    fn move_focus(&mut self, direction: Direction) {
+        if let Some(id) = self.neighbour(direction) {
+            self.focus = id;
+        }
+    }
+
+    fn focus_next(&mut self) {
+        let next = self.panes.range(self.focus + 1..).next().or_else(|| self.panes.iter().next());
+        if let Some((id, _)) = next {
+            self.focus = *id;
+            if self.zoomed {
+                self.relayout();
+            }
+        }
+    }
+
+    fn resize_focused(&mut self, direction: Direction) {
+        let (orientation, delta) = match direction {
+            Direction::Left => (Orientation::SideBySide, -RESIZE_STEP),
+            Direction::Right => (Orientation::SideBySide, RESIZE_STEP),
+            Direction::Up => (Orientation::Stacked, -RESIZE_STEP),
+            Direction::Down => (Orientation::Stacked, RESIZE_STEP),
+        };
+        if let Some(layout) = &mut self.layout {
+            if layout.resize(self.focus, orientation, delta) {
+                self.relayout();
+            }
+        }
+    }
+
+    fn send_to_focused(&mut self, bytes: &[u8]) {
+        if let Some(pane) = self.panes.get_mut(&self.focus) {
+            pane.pty.send(bytes);
+        }
+    }
+
+    // Passes keystrokes to the focused pane, except for Ctrl+B and the command after it.
+    // Returns false when the user quits.
+    fn handle_input(&mut self, input: &[u8]) -> io::Result<bool> {
+        let mut forward = Vec::new();
+        let mut i = 0;
+        while i < input.len() {
+            let byte = input[i];
+            i += 1;
+            if !self.prefix {
+                if byte == PREFIX {
+                    self.prefix = true;
+                } else {
+                    forward.push(byte);
+                }
+                continue;
+            }
+            self.prefix = false;
+            self.send_to_focused(&forward);
+            forward.clear();
+
+            // Arrow keys arrive as ESC [ A..D, or ESC O A..D in application mode
+            let mut key = byte;
+            if byte == 0x1B && matches!(input.get(i), Some(b'[' | b'O')) {
+                if let Some(arrow @ b'A'..=b'D') = input.get(i + 1) {
+                    key = match arrow { b'A' => b'k', b'B' => b'j', b'C' => b'l', _ => b'h' };
+                    i += 2;
+                }
+            }
+            match key {
+                PREFIX => forward.push(PREFIX),
+                b'%' | b'|' => self.split(Orientation::SideBySide)?,
+                b'"' | b'-' => self.split(Orientation::Stacked)?,
+                b'h' => self.move_focus(Direction::Left),
+                b'l' => self.move_focus(Direction::Right),
+                b'k' => self.move_focus(Direction::Up),
+                b'j' => self.move_focus(Direction::Down),
+                b'H' => self.resize_focused(Direction::Left),
+                b'L' => self.resize_focused(Direction::Right),
+                b'K' => self.resize_focused(Direction::Up),
+                b'J' => self.resize_focused(Direction::Down),
+                b'o' => self.focus_next(),
+                b'z' => {
+                    self.zoomed = !self.zoomed;
+                    self.relayout();
+                }
+                b'x' => self.close(self.focus),
+                b'd' | b'q' => return Ok(false),
+                b'?' => self.show_keys = !self.show_keys,
+                _ => {}
+            }
+        }
+        self.send_to_focused(&forward);
+        Ok(true)
+    }
+
+    // Feeds a pane's output to its screen. Returns false once the pane's session has ended.
+    fn read_pane(&mut self, id: usize, out: &mut impl Write) -> io::Result<bool> {
+        let Some(pane) = self.panes.get_mut(&id) else { return Ok(false) };
+        let mut buf = [0u8; BUFFER_SIZE];
+        match pane.pty.master.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                pane.screen.process(&buf[..n]);
+                let replies = pane.screen.callbacks_mut();
+                let (to_program, to_terminal) = (std::mem::take(&mut replies.to_program), std::mem::take(&mut replies.to_terminal));
+                pane.pty.send(&to_program);
+                out.write_all(&to_terminal)?;
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock) => Ok(true),
+            // EOF or EIO: nothing has the pane's pty open any more
+            _ => Ok(false),
+        }
+    }
+
+    fn draw_status(&self, out: &mut Vec<u8>) -> io::Result<()> {
+        let (cols, rows) = self.size;
+        queue!(out, MoveTo(0, rows.saturating_sub(1)), Clear(ClearType::CurrentLine))?;
+        if self.show_keys {
+            let keys: String = KEYS_HELP.chars().take(cols as usize).collect();
+            return write!(out, "{}", keys.dimmed());
+        }
+        let mut used = 0;
+        for (id, pane) in &self.panes {
+            let label = format!(" {}:{}{} ", id + 1, pane.session, if *id == self.focus && self.zoomed { " (zoom)" } else { "" });
+            used += label.width();
+            if used > cols as usize {
+                break;
+            }
+            if *id == self.focus {
+                write!(out, "{}", label.black().on_bright_cyan())?;
+            } else {
+                write!(out, "{}", label.bright_cyan())?;
+            }
+        }
+        let hint = if self.prefix { "^B …" } else { "^B ? keys" };
+        if used + hint.width() < cols as usize {
+            queue!(out, MoveTo(cols - hint.width() as u16, rows.saturating_sub(1)))?;
+            write!(out, "{}", hint.dimmed())?;
+        }
+        Ok(())
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut frame = Vec::new();
+        queue!(frame, Hide)?;
+        for id in &self.visible {
+            if let Some(pane) = self.panes.get(id) {
+                pane.draw(&mut frame)?;
+            }
+        }
+        for (orientation, rect) in &self.borders {
+            for row in rect.y..rect.y + rect.height {
+                queue!(frame, MoveTo(rect.x, row))?;
+                let line = match orientation {
+                    Orientation::SideBySide => "│".to_string(),
+                    Orientation::Stacked => "─".repeat(rect.width as usize),
+                };
+                write!(frame, "{}", line.dimmed())?;
+            }
+        }
+        self.draw_status(&mut frame)?;
+
+        // The real cursor and key modes follow the focused pane
+        if let Some(pane) = self.panes.get(&self.focus) {
+            let screen = pane.screen.screen();
+            let (row, col) = screen.cursor_position();
+            write!(frame, "\x1B[?1{}", if screen.application_cursor() { 'h' } else { 'l' })?;
+            write!(frame, "\x1B[?2004{}", if screen.bracketed_paste() { 'h' } else { 'l' })?;
+            queue!(frame, MoveTo(pane.rect.x + col, pane.rect.y + row))?;
+            if !screen.hide_cursor() {
+                queue!(frame, Show)?;
+            }
+        }
+        out.write_all(&frame)?;
+        out.flush()
+    }
+
+    fn run_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let mut buf = [0u8; BUFFER_SIZE];
+        let mut dirty = true;
+        loop {
+            if self.panes.is_empty() {
+                return Ok(());
+            }
+            if dirty {
+                self.draw(out)?;
+                dirty = false;
+            }
+
+            let ids: Vec<usize> = self.panes.keys().copied().collect();
+            let mut fds: Vec<libc::pollfd> = std::iter::once(libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 })
+                .chain(ids.iter().map(|id| {
+                    let pty = &self.panes[id].pty;
+                    libc::pollfd { fd: pty.master.as_raw_fd(), events: pty.poll_events(), revents: 0 }
+                }))
+                .collect();
+            // SAFETY: `fds` is a valid array of fds.len() pollfds
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, POLL_INTERVAL_MS) } == -1 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            let mut ended = Vec::new();
+            for (id, fd) in ids.iter().zip(&fds[1..]) {
+                if fd.revents & libc::POLLOUT != 0 {
+                    if let Some(pane) = self.panes.get_mut(id) {
+                        pane.pty.flush_input();
+                    }
+                }
+                if fd.revents & !libc::POLLOUT != 0 {
+                    dirty = true;
+                    if !self.read_pane(*id, out)? {
+                        ended.push(*id);
+                    }
+                }
+            }
+            // A session can end while something it started still holds the pty open
+            ended.extend(ids.iter().filter(|id| self.panes.get(id).is_some_and(|pane| matches!(try_wait_child(&pane.pty.child), Ok(Some(_))))));
+            for id in ended {
+                self.close(id);
+            }
+
+            if fds[0].revents != 0 {
+                // SAFETY: `buf` is valid for BUFFER_SIZE bytes
+                let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), BUFFER_SIZE) };
+                if n <= 0 || !self.handle_input(&buf[..n as usize])? {
+                    return Ok(());
+                }
+                dirty = true;
+            }
+
+            let size = terminal::size().unwrap_or(self.size);
+            if size != self.size {
+                self.size = size;
+                self.relayout();
+                dirty = true;
+            }
+        }
+    }
+}
+
+// Full-screen split view with one Partermai session per pane, each on its own pty. Starts
+// with a new session for each of `sessions`, side by side, in the current session's
+// directory. Without names there is one pane, started from the current session's profile.
+pub fn run(sessions: &[String], current: &crate::Session) -> io::Result<()> {
+    if env::var_os(NESTED_VAR).is_some() {
+        return Err(io::Error::other("Already inside the multiplexer"));
+    }
+    let mut mux = Mux {
+        cwd: &current.env.current_dir,
+        profiles: BTreeMap::new(),
+        panes: BTreeMap::new(),
+        layout: None,
+        borders: Vec::new(),
+        visible: Vec::new(),
+        focus: 0,
+        next_id: 0,
+        zoomed: false,
+        prefix: false,
+        show_keys: false,
+        size: terminal::size().unwrap_or((80, 24)),
+    };
+    let sessions = match sessions {
+        [] => {
+            let name = mux.new_session_name();
+            if let Some(profile) = &current.profile {
+                mux.profiles.insert(name.clone(), profile.clone());
+            }
+            vec![name]
+        }
+        names => names.to_vec(),
+    };
+    for session in sessions {
+        let id = mux.open_pane(session)?;
+        match &mut mux.layout {
+            Some(layout) => layout.split(mux.focus, id, Orientation::SideBySide),
+            None => mux.layout = Some(Layout::Pane(id)),
+        }
+        mux.focus = id;
+    }
+    mux.relayout();
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+
+    let result = mux.run_loop(&mut stdout);
+    for pane in mux.panes.values_mut() {
+        pane.pty.kill();
+    }
+
+    write!(stdout, "\x1B[?1l\x1B[?2004l")?;
+    execute!(stdout, LeaveAlternateScreen, Show)?;
+    terminal::disable_raw_mode()?;
+    result
+}
@@ -0,0 +1,361 @@
+use std::env;
+use std::io::{self, IsTerminal, StdoutLock, Write};
+use std::process::{Child, Command, Stdio};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use unicode_width::UnicodeWidthChar;
+use crate::config::PagerConfig;
+
+// Output larger than this is printed directly rather than held for the internal pager
+const MAX_BUFFERED: usize = 32 * 1024 * 1024;
+
+enum Sink {
+    Buffering(Vec<u8>),
+    External(Child),
+    Direct(StdoutLock<'static>),
+}
+
+// Collects command output and pages it when it doesn't fit on the screen
+pub struct Pager<'a> {
+    config: &'a PagerConfig,
+    sink: Sink,
+    newlines: usize,
+}
+
+impl<'a> Pager<'a> {
+    pub fn new(config: &'a PagerConfig) -> Self {
+        let sink = if config.enabled && io::stdout().is_terminal() {
+            Sink::Buffering(Vec::new())
+        } else {
+            Sink::Direct(io::stdout().lock())
+        };
+        Self { config, sink, newlines: 0 }
+    }
+
+    // Shows whatever was written, paging it if needed
+    pub fn finish(self) -> io::Result<()> {
+        match self.sink {
+            Sink::Buffering(buf) => {
+                let (cols, rows) = terminal_size();
+                let text = String::from_utf8_lossy(&buf);
+                if wrap_lines(&text, cols).len() < rows {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(&buf)?;
+                    return stdout.flush();
+                }
+                if self.config.use_external {
+                    if let Some(mut child) = spawn_external() {
+                        if let Some(stdin) = child.stdin.as_mut() {
+                            ignore_broken_pipe(stdin.write_all(&buf))?;
+                        }
+                        return wait_external(child);
+                    }
+                }
+                run_internal(&text)
+            }
+            Sink::External(child) => wait_external(child),
+            Sink::Direct(mut stdout) => stdout.flush(),
+        }
+    }
+}
+
+impl Write for Pager<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match &mut self.sink {
+            Sink::Buffering(buf) => {
+                buf.extend_from_slice(data);
+                self.newlines += data.iter().filter(|&&b| b == b'\n').count();
+
+                // $PAGER can stream, so hand over as soon as the output is taller than the screen
+                if self.config.use_external && self.newlines >= terminal_size().1 {
+                    if let Some(mut child) = spawn_external() {
+                        if let Some(stdin) = child.stdin.as_mut() {
+                            ignore_broken_pipe(stdin.write_all(buf))?;
+                        }
+                        self.sink = Sink::External(child);
+                    }
+                } else if buf.len() > MAX_BUFFERED {
+                    let mut stdout = io::stdout().lock();
+                    stdout.write_all(buf)?;
+                    self.sink = Sink::Direct(stdout);
+                }
+                Ok(data.len())
+            }
+            Sink::External(child) => {
+                if let Some(stdin) = child.stdin.as_mut() {
+                    ignore_broken_pipe(stdin.write_all(data))?;
+                }
+                Ok(data.len())
+            }
+            Sink::Direct(stdout) => stdout.write(data),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Direct(stdout) => stdout.flush(),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Quitting the pager early closes its stdin; that's not an error for us
+fn ignore_broken_pipe(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        other => other,
+    }
+}
+
+fn terminal_size() -> (usize, usize) {
+    terminal::size()
+        .map(|(cols, rows)| (cols.max(1) as usize, rows.max(2) as usize))
+        .unwrap_or((80, 24))
+}
+
+fn spawn_external() -> Option<Child> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| String::from("less -R"));
+    Command::new("sh")
+        .args(["-c", &pager])
+        .stdin(Stdio::piped())
+        .spawn()
+        .ok()
+}
+
+fn wait_external(mut child: Child) -> io::Result<()> {
+    drop(child.stdin.take());
+    child.wait()?;
+    Ok(())
+}
+
+// A screen row: the text with its escape sequences, plus the same text without them for searching
+struct Row {
+    styled: String,
+    plain: String,
+}
+
+// Splits text into rows no wider than `cols`, carrying SGR colors across wrapped rows
+fn wrap_lines(text: &str, cols: usize) -> Vec<Row> {
+    let mut rows = Vec::new();
+
+    for line in text.lines() {
+        let mut styled = String::new();
+        let mut plain = String::new();
+        let mut width = 0;
+        let mut active_sgr = String::new();
+        let mut chars = line.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '\x1B' {
+                let mut seq = String::from(c);
+                if chars.peek() == Some(&'[') {
+                    seq.push(chars.next().unwrap_or('['));
+                    for next in chars.by_ref() {
+                        seq.push(next);
+                        if ('\x40'..='\x7E').contains(&next) {
+                            break;
+                        }
+                    }
+                }
+                if seq.ends_with('m') {
+                    if seq == "\x1B[0m" || seq == "\x1B[m" {
+                        active_sgr.clear();
+                    } else {
+                        active_sgr.push_str(&seq);
+                    }
+                }
+                styled.push_str(&seq);
+                continue;
+            }
+
+            // A tab needs at least one column, so it wraps like any other character first
+            let char_width = if c == '\t' { 1 } else { c.width().unwrap_or(0) };
+            if width + char_width > cols {
+                styled.push_str("\x1B[0m");
+                rows.push(Row { styled: std::mem::take(&mut styled), plain: std::mem::take(&mut plain) });
+                styled.push_str(&active_sgr);
+                width = 0;
+            }
+
+            // Tabs are expanded here since the terminal's tab stops don't know about wrapping
+            if c == '\t' {
+                let spaces = (8 - width % 8).min(cols - width);
+                styled.push_str(&" ".repeat(spaces));
+                plain.push_str(&" ".repeat(spaces));
+                width += spaces;
+                continue;
+            }
+            styled.push(c);
+            plain.push(c);
+            width += char_width;
+        }
+
+        styled.push_str("\x1B[0m");
+        rows.push(Row { styled, plain });
+    }
+    rows
+}
+
+struct PagerView {
+    rows: Vec<Row>,
+    top: usize,
+    height: usize,
+    search: Option<String>,
+    message: Option<String>,
+}
+
+impl PagerView {
+    fn max_top(&self) -> usize {
+        self.rows.len().saturating_sub(self.height)
+    }
+
+    fn scroll_by(&mut self, delta: isize) {
+        self.top = self.top.saturating_add_signed(delta).min(self.max_top());
+    }
+
+    // Moves to the next (or previous) row containing the search pattern
+    fn find(&mut self, forward: bool, include_top: bool) {
+        let Some(pattern) = self.search.as_ref().filter(|p| !p.is_empty()) else { return };
+        let start = if include_top { self.top } else { self.top + 1 };
+        let found = if forward {
+            (start..self.rows.len()).find(|&i| self.rows[i].plain.contains(pattern.as_str()))
+        } else {
+            (0..self.top).rev().find(|&i| self.rows[i].plain.contains(pattern.as_str()))
+        };
+        match found {
+            Some(row) => self.top = row.min(self.max_top()),
+            None => self.message = Some(format!("Pattern not found: {}", pattern)),
+        }
+    }
+
+    fn draw(&self, out: &mut impl Write) -> io::Result<()> {
+        queue!(out, MoveTo(0, 0), Clear(ClearType::All))?;
+        for (i, row) in self.rows.iter().skip(self.top).take(self.height).enumerate() {
+            queue!(out, MoveTo(0, i as u16))?;
+            match &self.search {
+                Some(pattern) if !pattern.is_empty() && row.plain.contains(pattern.as_str()) => {
+                    // Matching rows lose their colors so the match can be shown in reverse video
+                    let marked = row.plain.replace(pattern.as_str(), &format!("\x1B[7m{}\x1B[27m", pattern));
+                    write!(out, "{}", marked)?;
+                }
+                _ => write!(out, "{}", row.styled)?,
+            }
+        }
+
+        let status = match &self.message {
+            Some(message) => message.clone(),
+            None if self.top >= self.max_top() => String::from("(END)"),
+            None => format!(":{}%", (self.top + self.height) * 100 / self.rows.len().max(1)),
+        };
+        queue!(out, MoveTo(0, self.height as u16))?;
+        write!(out, "\x1B[7m{}\x1B[0m", status)?;
+        out.flush()
+    }
+
+    // Reads a search pattern on the status line; None if cancelled with Esc
+    fn prompt_search(&self, out: &mut impl Write) -> io::Result<Option<String>> {
+        let mut pattern = String::new();
+        loop {
+            queue!(out, MoveTo(0, self.height as u16), Clear(ClearType::CurrentLine))?;
+            write!(out, "/{}", pattern)?;
+            out.flush()?;
+
+            if let Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) = event::read()? {
+                match code {
+                    KeyCode::Enter => return Ok(Some(pattern)),
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Backspace => {
+                        pattern.pop();
+                    }
+                    KeyCode::Char(c) => pattern.push(c),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+fn run_internal(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+
+    let result = page_loop(text, &mut stdout);
+
+    execute!(stdout, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn page_loop(text: &str, out: &mut impl Write) -> io::Result<()> {
+    let (cols, height) = terminal_size();
+    let mut view = PagerView { rows: wrap_lines(text, cols), top: 0, height: height - 1, search: None, message: None };
+
+    loop {
+        view.draw(out)?;
+        let page = view.height as isize;
+
+        match event::read()? {
+            Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) => {
+                view.message = None;
+                match code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => break,
+                    KeyCode::Down | KeyCode::Enter | KeyCode::Char('j') => view.scroll_by(1),
+                    KeyCode::Up | KeyCode::Char('k') => view.scroll_by(-1),
+                    KeyCode::PageDown | KeyCode::Char(' ') | KeyCode::Char('f') => view.scroll_by(page),
+                    KeyCode::PageUp | KeyCode::Char('b') => view.scroll_by(-page),
+                    KeyCode::Char('d') => view.scroll_by(page / 2),
+                    KeyCode::Char('u') => view.scroll_by(-page / 2),
+                    KeyCode::Home | KeyCode::Char('g') => view.top = 0,
+                    KeyCode::End | KeyCode::Char('G') => view.top = view.max_top(),
+                    KeyCode::Char('/') => {
+                        if let Some(pattern) = view.prompt_search(out)? {
+                            view.search = Some(pattern);
+                            view.find(true, true);
+                        }
+                    }
+                    KeyCode::Char('n') => view.find(true, false),
+                    KeyCode::Char('N') => view.find(false, false),
+                    _ => {}
+                }
+            }
+            Event::Resize(cols, rows) => {
+                view.rows = wrap_lines(text, cols.max(1) as usize);
+                view.height = (rows.max(2) - 1) as usize;
+                view.top = view.top.min(view.max_top());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_lines_splits_at_the_width() {
+        let rows = wrap_lines("abcdef\nxy", 4);
+        let plain: Vec<&str> = rows.iter().map(|row| row.plain.as_str()).collect();
+        assert_eq!(plain, ["abcd", "ef", "xy"]);
+    }
+
+    #[test]
+    fn wrap_lines_carries_colors_onto_wrapped_rows() {
+        let rows = wrap_lines("\x1B[31mabcdef", 4);
+        assert!(rows[1].styled.starts_with("\x1B[31m"));
+    }
+
+    #[test]
+    fn wrap_lines_wraps_tabs_at_the_edge() {
+        // A full row followed by tabs used to push the width past the edge and underflow
+        let rows = wrap_lines("abcd\t\tx", 4);
+        assert!(rows.iter().all(|row| row.plain.chars().count() <= 4));
+        assert_eq!(rows[0].plain, "abcd");
+        assert!(rows.last().unwrap().plain.ends_with('x'));
+    }
+}
@@ -0,0 +1,232 @@
+use std::io::{self, Write};
+use colored::*;
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+// Scoring, roughly after fzf: matches are worth more at word starts and in runs
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 12;
+const PENALTY_GAP: i64 = 1;
+
+// What a full-screen picker chooses from: the Ctrl+T finder and the Ctrl+R history
+pub trait PickerSource {
+    // The text an item is matched against and returned as when picked
+    fn text(&self, index: usize) -> &str;
+    // The items on offer right now; among equal scores they keep this order
+    fn filtered(&self) -> Vec<usize>;
+    // The dimmed line under the query, given how many items match
+    fn status(&self, matches: usize) -> String;
+    // Shown before and after an item's text; the text is cut to fit between them
+    fn decorate(&self, _index: usize) -> (ColoredString, ColoredString) {
+        ("".normal(), "".normal())
+    }
+    // Keys the picker itself doesn't use
+    fn handle_key(&mut self, _code: KeyCode, _ctrl: bool) -> KeyAction {
+        KeyAction::Ignore
+    }
+}
+
+pub enum KeyAction {
+    Ignore,
+    Refilter, // What `filtered` offers has changed
+    Move(isize),
+}
+
+// Scores `candidate` if it contains the query's characters in order, and returns the
+// positions (in chars) of the matched characters for highlighting. Smart case: the
+// match is case-sensitive only when the query has an uppercase letter.
+fn fuzzy_match(candidate: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let normalize = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut positions = Vec::with_capacity(query.len());
+    let mut score = 0;
+    let mut next = 0;
+
+    for wanted in query.chars().map(normalize) {
+        let found = (next..chars.len()).find(|&i| normalize(chars[i]) == wanted)?;
+        score += SCORE_MATCH;
+        let at_boundary = found == 0 || matches!(chars[found - 1], '/' | '_' | '-' | '.' | ' ');
+        if at_boundary {
+            score += BONUS_BOUNDARY;
+        }
+        if found > 0 && positions.last() == Some(&(found - 1)) {
+            score += BONUS_CONSECUTIVE;
+        } else if !positions.is_empty() {
+            score -= PENALTY_GAP * (found - next) as i64;
+        }
+        positions.push(found);
+        next = found + 1;
+    }
+    Some((score, positions))
+}
+
+// Best matches first; the sort is stable, so ties keep the order they were offered in
+fn rank<'a>(items: impl Iterator<Item = (usize, &'a str)>, query: &str) -> Vec<(usize, Vec<usize>)> {
+    let mut ranked: Vec<(i64, usize, Vec<usize>)> = items
+        .filter_map(|(i, text)| fuzzy_match(text, query).map(|(score, positions)| (score, i, positions)))
+        .collect();
+    ranked.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+    ranked.into_iter().map(|(_, i, positions)| (i, positions)).collect()
+}
+
+fn highlight(candidate: &str, positions: &[usize], width: usize) -> String {
+    let mut shown = String::new();
+    let mut used = 0;
+    for (i, c) in candidate.chars().enumerate() {
+        used += c.width().unwrap_or(0);
+        if used > width {
+            break;
+        }
+        if positions.contains(&i) {
+            shown.push_str(&c.to_string().bright_green().bold().to_string());
+        } else {
+            shown.push(c);
+        }
+    }
+    shown
+}
+
+struct Picker<S> {
+    source: S,
+    query: String,
+    matches: Vec<(usize, Vec<usize>)>,
+    selected: usize,
+    top: usize,
+}
+
+impl<S: PickerSource> Picker<S> {
+    fn update_matches(&mut self) {
+        let source = &self.source;
+        self.matches = rank(source.filtered().into_iter().map(|i| (i, source.text(i))), &self.query);
+        self.selected = 0;
+        self.top = 0;
+    }
+
+    fn draw(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let (cols, rows) = terminal::size().unwrap_or((80, 24));
+        let (cols, list_height) = (cols as usize, (rows as usize).saturating_sub(2).max(1));
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + list_height {
+            self.top = self.selected + 1 - list_height;
+        }
+
+        queue!(out, MoveTo(0, 0), Clear(ClearType::All))?;
+        write!(out, "{} {}", ">".bright_cyan().bold(), self.query)?;
+        queue!(out, MoveTo(0, 1))?;
+        write!(out, "{}", self.source.status(self.matches.len()).dimmed())?;
+
+        for (row, (index, positions)) in self.matches.iter().enumerate().skip(self.top).take(list_height) {
+            let (before, after) = self.source.decorate(*index);
+            // Widths of the plain text; ColoredString derefs to it
+            let room = cols.saturating_sub(before.width() + after.width() + 2);
+            queue!(out, MoveTo(0, (row - self.top + 2) as u16))?;
+            let line = highlight(self.source.text(*index), positions, room);
+            if row == self.selected {
+                write!(out, "{}{}\x1B[7m{}\x1B[0m{}", "▌".bright_cyan(), before, line, after)?;
+            } else {
+                write!(out, " {}{}{}", before, line, after)?;
+            }
+        }
+        queue!(out, MoveTo((self.query.width() + 2) as u16, 0))?;
+        out.flush()
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.matches.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last.max(0)) as usize;
+    }
+
+    fn run_loop(&mut self, out: &mut impl Write) -> io::Result<Option<String>> {
+        loop {
+            self.draw(out)?;
+            // Any other event, such as a resize, just redraws
+            if let Event::Key(KeyEvent { code, modifiers, kind: KeyEventKind::Press, .. }) = event::read()? {
+                let ctrl = modifiers.contains(KeyModifiers::CONTROL);
+                match code {
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Char('c' | 'g') if ctrl => return Ok(None),
+                    KeyCode::Enter => {
+                        return Ok(self.matches.get(self.selected).map(|(i, _)| self.source.text(*i).to_string()));
+                    }
+                    KeyCode::Up => self.move_selection(-1),
+                    KeyCode::Char('p' | 'k') if ctrl => self.move_selection(-1),
+                    KeyCode::Down => self.move_selection(1),
+                    KeyCode::Char('n' | 'j') if ctrl => self.move_selection(1),
+                    KeyCode::PageUp => self.move_selection(-10),
+                    KeyCode::PageDown => self.move_selection(10),
+                    KeyCode::Char('u') if ctrl => {
+                        self.query.clear();
+                        self.update_matches();
+                    }
+                    KeyCode::Backspace => {
+                        self.query.pop();
+                        self.update_matches();
+                    }
+                    KeyCode::Char(c) if !ctrl => {
+                        self.query.push(c);
+                        self.update_matches();
+                    }
+                    _ => match self.source.handle_key(code, ctrl) {
+                        KeyAction::Ignore => {}
+                        KeyAction::Refilter => self.update_matches(),
+                        KeyAction::Move(delta) => self.move_selection(delta),
+                    },
+                }
+            }
+        }
+    }
+}
+
+// Full-screen fuzzy search over `source`, starting from `query`; None if the user cancels
+pub fn pick(source: impl PickerSource, query: &str) -> io::Result<Option<String>> {
+    let mut picker = Picker { source, query: query.to_string(), matches: Vec::new(), selected: 0, top: 0 };
+    picker.update_matches();
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen)?;
+
+    let result = picker.run_loop(&mut stdout);
+
+    execute!(stdout, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzzy_match_finds_characters_in_order() {
+        let (_, positions) = fuzzy_match("src/main.rs", "smr").unwrap();
+        assert_eq!(positions, vec![0, 4, 9]);
+        assert!(fuzzy_match("src/main.rs", "nm").is_none());
+    }
+
+    #[test]
+    fn fuzzy_match_uses_smart_case() {
+        assert!(fuzzy_match("README.md", "readme").is_some());
+        assert!(fuzzy_match("readme.md", "README").is_none());
+    }
+
+    #[test]
+    fn rank_prefers_consecutive_and_boundary_matches() {
+        let ranked = rank(["xmxaxin", "src/main.rs"].into_iter().enumerate(), "main");
+        assert_eq!(ranked[0].0, 1);
+        let ranked = rank(["lib/domain.rs", "main.rs"].into_iter().enumerate(), "main");
+        assert_eq!(ranked[0].0, 1);
+    }
+
+    #[test]
+    fn rank_keeps_offered_order_for_ties() {
+        let ranked = rank([(2, "a.rs"), (0, "b.rs"), (1, "c.rs")].into_iter(), "");
+        assert_eq!(ranked.iter().map(|(i, _)| *i).collect::<Vec<_>>(), vec![2, 0, 1]);
+    }
+}
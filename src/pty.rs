@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use crossterm::cursor::Show;
+use crossterm::{execute, terminal};
+use crate::timing::{self, try_wait_child, wait_child};
+
+// How long to wait for input before checking for resizes and whether the program exited
+const POLL_INTERVAL_MS: i32 = 50;
+const BUFFER_SIZE: usize = 8192;
+
+fn winsize(rows: u16, cols: u16) -> libc::winsize {
+    libc::winsize { ws_row: rows, ws_col: cols, ws_xpixel: 0, ws_ypixel: 0 }
+}
+
+fn window_size() -> libc::winsize {
+    let (cols, rows) = terminal::size().unwrap_or((80, 24));
+    winsize(rows, cols)
+}
+
+pub fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result == -1 { Err(io::Error::last_os_error()) } else { Ok(result) }
+}
+
+// A new pseudo-terminal of the given size, as (master, slave)
+fn open_pty(mut size: libc::winsize) -> io::Result<(OwnedFd, OwnedFd)> {
+    let (mut master, mut slave) = (-1, -1);
+    // SAFETY: openpty only writes the two descriptors, which we take ownership of; the size
+    // is passed as *mut because some platforms declare it that way
+    unsafe {
+        check(libc::openpty(&mut master, &mut slave, std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::addr_of_mut!(size)))?;
+        let (master, slave) = (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave));
+        // Programs we start get the slave as stdio and shouldn't inherit anything else
+        check(libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC))?;
+        check(libc::fcntl(slave.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC))?;
+        // Partermai has no job control, so a program stopped with Ctrl+Z could never be
+        // resumed; the suspend key is passed through as an ordinary byte instead
+        let mut termios = std::mem::zeroed::<libc::termios>();
+        check(libc::tcgetattr(slave.as_raw_fd(), &mut termios))?;
+        termios.c_cc[libc::VSUSP] = libc::_POSIX_VDISABLE;
+        check(libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios))?;
+        Ok((master, slave))
+    }
+}
+
+// A pseudo-terminal of `rows` by `cols` with nothing running on it yet, as (master, slave)
+pub fn open_sized(rows: u16, cols: u16) -> io::Result<(OwnedFd, OwnedFd)> {
+    open_pty(winsize(rows, cols))
+}
+
+// The kernel sends the program on the pty SIGWINCH when it changes size
+pub fn resize_pty(master: &impl AsRawFd, rows: u16, cols: u16) {
+    let size = winsize(rows, cols);
+    // SAFETY: TIOCSWINSZ reads a winsize from the pointer
+    unsafe { libc::ioctl(master.as_raw_fd(), libc::TIOCSWINSZ, &size) };
+}
+
+// A program running on its own pseudo-terminal
+pub struct PtyChild {
+    pub child: Child,
+    pub master: File, // Reads what the program prints, writes what it reads
+    pending: Vec<u8>, // Input the program isn't ready for yet, once the master is non-blocking
+}
+
+// Starts `command` as the foreground process group of a fresh pseudo-terminal sized like
+// ours. Errors are the same io::Errors a plain spawn gives, so callers can tell "not found"
+// from "not executable".
+pub fn spawn(command: Command) -> io::Result<PtyChild> {
+    spawn_command(command, window_size())
+}
+
+// Like `spawn`, on a pty of `rows` by `cols`
+pub fn spawn_sized(command: Command, rows: u16, cols: u16) -> io::Result<PtyChild> {
+    spawn_command(command, winsize(rows, cols))
+}
+
+fn spawn_command(mut command: Command, size: libc::winsize) -> io::Result<PtyChild> {
+    let (master, slave) = open_pty(size)?;
+    command.stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    // SAFETY: only async-signal-safe calls run between fork and exec
+    unsafe {
+        command.pre_exec(|| {
+            // A session of its own with the pty as controlling terminal: Ctrl+C, Ctrl+Z
+            // and SIGWINCH now go to the program rather than to Partermai
+            check(libc::setsid())?;
+            check(libc::ioctl(0, libc::TIOCSCTTY as _, 0))?;
+            Ok(())
+        });
+    }
+    let child = command.spawn()?;
+    // `command` still holds the slave; dropping it lets us see EOF once the program exits
+    drop(command);
+    Ok(PtyChild { child, master: File::from(master), pending: Vec::new() })
+}
+
+impl PtyChild {
+    // Reading and writing the master no longer wait, so a program that stops reading can't
+    // hold up the loop relaying its output (or other ptys'). Input then goes through `send`.
+    pub fn set_nonblocking(&self) -> io::Result<()> {
+        let fd = self.master.as_raw_fd();
+        // SAFETY: F_GETFL and F_SETFL only read and change the descriptor's flags
+        unsafe {
+            let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+            check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+        }
+        Ok(())
+    }
+
+    // Queues input for the program and writes as much of it as the pty takes now
+    pub fn send(&mut self, bytes: &[u8]) {
+        self.pending.extend_from_slice(bytes);
+        self.flush_input();
+    }
+
+    // Writes queued input until the pty is full. Input for a program that has gone away is
+    // dropped.
+    pub fn flush_input(&mut self) {
+        while !self.pending.is_empty() {
+            match self.master.write(&self.pending) {
+                Ok(n) if n > 0 => {
+                    self.pending.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                _ => {
+                    self.pending.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    // What to poll the master for: output, and room for input while some is queued
+    pub fn poll_events(&self) -> libc::c_short {
+        if self.pending.is_empty() { libc::POLLIN } else { libc::POLLIN | libc::POLLOUT }
+    }
+
+    pub fn resize(&self, rows: u16, cols: u16) {
+        resize_pty(&self.master, rows, cols);
+    }
+
+    // Stops the program unless it has already exited
+    pub fn kill(&mut self) {
+        if let Ok(None) = try_wait_child(&self.child) {
+            let _ = self.child.kill();
+            let _ = wait_child(&self.child);
+        }
+    }
+
+    // Relays the terminal to the program until it exits, then puts our terminal back
+    pub fn wait(mut self) -> io::Result<ExitStatus> {
+        terminal::enable_raw_mode()?;
+        let result = self.relay();
+        // Whatever mode the program left the screen in, the prompt needs a visible cursor
+        let _ = execute!(io::stdout(), Show);
+        terminal::disable_raw_mode()?;
+        // Like other shells, start the prompt on a fresh line after Ctrl+C and friends
+        if result.as_ref().is_ok_and(|status| status.signal().is_some()) {
+            println!();
+        }
+        result
+    }
+
+    fn relay(&mut self) -> io::Result<ExitStatus> {
+        // A paste bigger than the pty takes would otherwise block until the program reads
+        // it, with its output and our Ctrl+C stuck behind it
+        self.set_nonblocking()?;
+        let mut stdout = io::stdout();
+        let mut buf = [0u8; BUFFER_SIZE];
+        let master_fd = self.master.as_raw_fd();
+        let mut size = window_size();
+        let mut stdin_open = true;
+
+        loop {
+            let mut fds = [
+                libc::pollfd { fd: libc::STDIN_FILENO, events: if stdin_open { libc::POLLIN } else { 0 }, revents: 0 },
+                libc::pollfd { fd: master_fd, events: self.poll_events(), revents: 0 },
+            ];
+            // SAFETY: `fds` is a valid array of two pollfds
+            if let Err(e) = check(unsafe { libc::poll(fds.as_mut_ptr(), 2, POLL_INTERVAL_MS) }) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+
+            if fds[1].revents & libc::POLLOUT != 0 {
+                self.flush_input();
+            }
+            if fds[1].revents & !libc::POLLOUT != 0 {
+                match self.master.read(&mut buf) {
+                    Ok(n) if n > 0 => {
+                        stdout.write_all(&buf[..n])?;
+                        stdout.flush()?;
+                    }
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted) => {}
+                    // EOF or EIO: nothing has the slave open any more, so the program is done
+                    _ => return wait_child(&self.child),
+                }
+            }
+
+            if fds[0].revents != 0 {
+                // Read the descriptor directly; std's stdin buffer would hold on to keystrokes
+                // SAFETY: `buf` is valid for BUFFER_SIZE bytes
+                let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), BUFFER_SIZE) };
+                if n > 0 {
+                    let input = &buf[..n as usize];
+                    timing::note_input();
+                    self.send(input);
+                    if !self.pending.is_empty() {
+                        self.interrupt_backlog(input);
+                    }
+                } else {
+                    stdin_open = false;
+                }
+            }
+
+            let new_size = window_size();
+            if (new_size.ws_row, new_size.ws_col) != (size.ws_row, size.ws_col) {
+                size = new_size;
+                self.resize(size.ws_row, size.ws_col);
+            }
+
+            // Background jobs may keep the pty open after the program itself exits
+            if let Some(status) = try_wait_child(&self.child)? {
+                self.drain(&mut stdout)?;
+                return Ok(status);
+            }
+        }
+    }
+
+    // The pty only sees Ctrl+C once the input ahead of it has been read, which a program
+    // stuck on a paste never does. So when the key can't get through, throw the backlog away
+    // and send the signal ourselves, as the pty would have.
+    fn interrupt_backlog(&mut self, input: &[u8]) {
+        let fd = self.master.as_raw_fd();
+        // SAFETY: tcgetattr only writes the termios; on a master it reports the slave's
+        let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(fd, &mut termios) } == -1 || termios.c_lflag & libc::ISIG == 0 {
+            return;
+        }
+        let signal = input.iter().find_map(|&b| match b {
+            b if b == termios.c_cc[libc::VINTR] => Some(libc::SIGINT),
+            b if b == termios.c_cc[libc::VQUIT] => Some(libc::SIGQUIT),
+            _ => None,
+        });
+        let Some(signal) = signal else { return };
+        self.pending.clear();
+        // SAFETY: these only act on the pty and on the process group in its foreground
+        unsafe {
+            libc::tcflush(fd, libc::TCIOFLUSH);
+            let group = libc::tcgetpgrp(fd);
+            if group > 0 {
+                libc::kill(-group, signal);
+            }
+        }
+    }
+
+    // Copies out whatever output is still buffered in the pty
+    fn drain(&mut self, stdout: &mut impl Write) -> io::Result<()> {
+        let mut buf = [0u8; BUFFER_SIZE];
+        loop {
+            let mut fds = [libc::pollfd { fd: self.master.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+            // SAFETY: `fds` is a valid array of one pollfd
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), 1, 0) };
+            if ready <= 0 || fds[0].revents & libc::POLLIN == 0 {
+                return stdout.flush();
+            }
+            match self.master.read(&mut buf) {
+                Ok(n) if n > 0 => stdout.write_all(&buf[..n])?,
+                _ => return stdout.flush(),
+            }
+        }
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+use colored::*;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::terminal;
+use ignore::WalkBuilder;
+use regex::{Regex, RegexBuilder};
+use crate::args::{parse_args, report, usage, ParsedArgs};
+use crate::Environment;
+
+// Lines shown by head and tail without -n
+const DEFAULT_LINE_COUNT: usize = 10;
+// How often `tail -f` checks its files for new data
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+fn line_count(parsed: &ParsedArgs, cmd: &str) -> io::Result<usize> {
+    match parsed.value('n') {
+        Some(value) => value.parse().map_err(|_| io::Error::new(io::ErrorKind::InvalidInput,
+            format!("{}: invalid line count '{}'", cmd, value))),
+        None => Ok(DEFAULT_LINE_COUNT),
+    }
+}
+
+// Every command reads its file operands, or stdin when there are none (or for `-`)
+fn inputs<'a>(operands: &[&'a str]) -> Vec<&'a str> {
+    if operands.is_empty() { vec!["-"] } else { operands.to_vec() }
+}
+
+fn open_input(operand: &str, env: &Environment) -> io::Result<Box<dyn BufRead>> {
+    if operand == "-" {
+        return Ok(Box::new(io::stdin().lock()));
+    }
+    let path = env.resolve_path(operand);
+    if path.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Is a directory"));
+    }
+    Ok(Box::new(BufReader::new(File::open(path)?)))
+}
+
+fn display_name(operand: &str) -> &str {
+    if operand == "-" { "(standard input)" } else { operand }
+}
+
+// Lines without their trailing newline, decoded lossily so odd bytes don't abort a search
+fn lines(reader: Box<dyn BufRead>) -> impl Iterator<Item = io::Result<String>> {
+    reader.split(b'\n').map(|line| line.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+// Reads every line of every input, reporting inputs that can't be opened
+fn read_all_lines(cmd: &str, operands: &[&str], env: &Environment) -> io::Result<Vec<String>> {
+    let mut all = Vec::new();
+    for operand in inputs(operands) {
+        match open_input(operand, env) {
+            Ok(reader) => {
+                for line in lines(reader) {
+                    all.push(line?);
+                }
+            }
+            Err(e) => report(cmd, operand, &e),
+        }
+    }
+    Ok(all)
+}
+
+// `==> name <==` headers separate the output when several files are given
+fn write_header(out: &mut impl Write, name: &str, first: bool) -> io::Result<()> {
+    if !first {
+        writeln!(out)?;
+    }
+    writeln!(out, "{} {} {}", "==>".dimmed(), name.bright_cyan(), "<==".dimmed())
+}
+
+struct GrepOptions {
+    invert: bool,       // -v
+    line_numbers: bool, // -n
+    count: bool,        // -c
+    files_only: bool,   // -l
+    with_names: bool,   // Several files or -r
+}
+
+// Wraps every match in color, leaving the rest of the line alone
+fn highlight_matches(line: &str, regex: &Regex) -> String {
+    let mut highlighted = String::with_capacity(line.len());
+    let mut last = 0;
+    for m in regex.find_iter(line).filter(|m| !m.is_empty()) {
+        highlighted.push_str(&line[last..m.start()]);
+        highlighted.push_str(&m.as_str().red().bold().to_string());
+        last = m.end();
+    }
+    highlighted.push_str(&line[last..]);
+    highlighted
+}
+
+fn grep_reader(reader: Box<dyn BufRead>, name: &str, regex: &Regex, options: &GrepOptions, out: &mut impl Write) -> io::Result<()> {
+    let prefix = |line_no: usize| {
+        let mut prefix = String::new();
+        if options.with_names {
+            prefix.push_str(&format!("{}{}", name.magenta(), ":".cyan()));
+        }
+        if options.line_numbers {
+            prefix.push_str(&format!("{}{}", line_no.to_string().green(), ":".cyan()));
+        }
+        prefix
+    };
+
+    let mut count = 0;
+    for (i, line) in reader.split(b'\n').enumerate() {
+        let bytes = line?;
+        let text = String::from_utf8_lossy(&bytes);
+        if regex.is_match(&text) == options.invert {
+            continue;
+        }
+        count += 1;
+        if options.files_only {
+            break;
+        }
+        if options.count {
+            continue;
+        }
+        // Matching lines of binary files would only garble the terminal
+        if bytes.contains(&0) {
+            writeln!(out, "Binary file {} matches", name)?;
+            return Ok(());
+        }
+        let shown = if options.invert { text.to_string() } else { highlight_matches(&text, regex) };
+        writeln!(out, "{}{}", prefix(i + 1), shown)?;
+    }
+
+    if options.files_only && count > 0 {
+        writeln!(out, "{}", name.magenta())?;
+    } else if options.count {
+        if options.with_names {
+            writeln!(out, "{}{}{}", name.magenta(), ":".cyan(), count)?;
+        } else {
+            writeln!(out, "{}", count)?;
+        }
+    }
+    Ok(())
+}
+
+// Searches a directory tree, skipping hidden files and anything .gitignore excludes
+fn grep_recursive(dir: &Path, env: &Environment, regex: &Regex, options: &GrepOptions, out: &mut impl Write) -> io::Result<()> {
+    let walker = WalkBuilder::new(dir)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    for entry in walker.filter_map(Result::ok) {
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let path = entry.path();
+        let name = match path.strip_prefix(&env.current_dir) {
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => env.tilde_path(path),
+        };
+        match File::open(path) {
+            Ok(file) => grep_reader(Box::new(BufReader::new(file)), &name, regex, options, out)?,
+            Err(e) => report("grep", &name, &e),
+        }
+    }
+    Ok(())
+}
+
+pub fn execute_grep(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("grep", args, "ivnclrwF", "")?;
+    let Some((pattern, operands)) = parsed.operands.split_first() else {
+        return Err(usage("grep [-ivnclrwF] <pattern> [file]..."));
+    };
+
+    let mut pattern = if parsed.has('F') { regex::escape(pattern) } else { pattern.to_string() };
+    if parsed.has('w') {
+        pattern = format!(r"\b(?:{})\b", pattern);
+    }
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(parsed.has('i'))
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("grep: {}", e)))?;
+
+    let recursive = parsed.has('r');
+    // `grep -r pattern` searches the current directory rather than stdin
+    let operands = if recursive && operands.is_empty() { vec!["."] } else { inputs(operands) };
+    let options = GrepOptions {
+        invert: parsed.has('v'),
+        line_numbers: parsed.has('n'),
+        count: parsed.has('c'),
+        files_only: parsed.has('l'),
+        with_names: recursive || operands.len() > 1,
+    };
+
+    for operand in operands {
+        let result = if recursive && operand != "-" && env.resolve_path(operand).is_dir() {
+            grep_recursive(&env.resolve_path(operand), env, &regex, &options, out)
+        } else {
+            open_input(operand, env).and_then(|reader| grep_reader(reader, display_name(operand), &regex, &options, out))
+        };
+        if let Err(e) = result {
+            report("grep", operand, &e);
+        }
+    }
+    Ok(())
+}
+
+pub fn execute_head(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("head", args, "", "n")?;
+    let count = line_count(&parsed, "head")?;
+    let operands = inputs(&parsed.operands);
+    let with_headers = operands.len() > 1;
+
+    for (i, operand) in operands.iter().enumerate() {
+        let reader = match open_input(operand, env) {
+            Ok(reader) => reader,
+            Err(e) => {
+                report("head", operand, &e);
+                continue;
+            }
+        };
+        if with_headers {
+            write_header(out, display_name(operand), i == 0)?;
+        }
+        for line in lines(reader).take(count) {
+            writeln!(out, "{}", line?)?;
+        }
+    }
+    Ok(())
+}
+
+// Keeps only the last `count` lines of a stream
+fn last_lines(reader: Box<dyn BufRead>, count: usize) -> io::Result<VecDeque<String>> {
+    let mut kept = VecDeque::with_capacity(count);
+    for line in lines(reader) {
+        if count == 0 {
+            continue;
+        }
+        if kept.len() == count {
+            kept.pop_front();
+        }
+        kept.push_back(line?);
+    }
+    Ok(kept)
+}
+
+struct FollowedFile {
+    name: String,
+    file: File,
+    position: u64,
+}
+
+// In raw mode the terminal no longer turns "\n" into a new line by itself
+fn write_raw(out: &mut impl Write, bytes: &[u8], raw: bool) -> io::Result<()> {
+    if !raw {
+        return out.write_all(bytes);
+    }
+    for chunk in bytes.split_inclusive(|&b| b == b'\n') {
+        match chunk.strip_suffix(b"\n") {
+            Some(line) => {
+                out.write_all(line)?;
+                out.write_all(b"\r\n")?;
+            }
+            None => out.write_all(chunk)?,
+        }
+    }
+    Ok(())
+}
+
+// True once the user presses q or Ctrl+C; without a terminal this just waits
+fn interrupted(raw: bool) -> io::Result<bool> {
+    if !raw {
+        std::thread::sleep(FOLLOW_INTERVAL);
+        return Ok(false);
+    }
+    if event::poll(FOLLOW_INTERVAL)? {
+        if let Event::Key(KeyEvent { code, modifiers, .. }) = event::read()? {
+            let ctrl_c = code == KeyCode::Char('c') && modifiers.contains(KeyModifiers::CONTROL);
+            return Ok(ctrl_c || code == KeyCode::Char('q'));
+        }
+    }
+    Ok(false)
+}
+
+// `current` is the file whose header was printed last
+fn follow_loop(files: &mut [FollowedFile], with_headers: bool, mut current: Option<usize>, raw: bool, out: &mut impl Write) -> io::Result<()> {
+    let mut buf = Vec::new();
+    while !interrupted(raw)? {
+        for (i, followed) in files.iter_mut().enumerate() {
+            let len = followed.file.metadata()?.len();
+            if len < followed.position {
+                write_raw(out, format!("{}: {}: file truncated\n", "tail".yellow(), followed.name).as_bytes(), raw)?;
+                followed.position = 0;
+            }
+            if len == followed.position {
+                continue;
+            }
+
+            followed.file.seek(SeekFrom::Start(followed.position))?;
+            buf.clear();
+            Read::by_ref(&mut followed.file).take(len - followed.position).read_to_end(&mut buf)?;
+            followed.position += buf.len() as u64;
+            if with_headers && current != Some(i) {
+                write_raw(out, format!("\n{} {} {}\n", "==>".dimmed(), followed.name.bright_cyan(), "<==".dimmed()).as_bytes(), raw)?;
+                current = Some(i);
+            }
+            write_raw(out, &buf, raw)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+// Prints whatever is appended to the files until q or Ctrl+C
+fn follow(files: &mut [FollowedFile], with_headers: bool, current: Option<usize>, out: &mut impl Write) -> io::Result<()> {
+    let raw = io::stdin().is_terminal();
+    if raw {
+        terminal::enable_raw_mode()?;
+    }
+    let result = follow_loop(files, with_headers, current, raw, out);
+    if raw {
+        terminal::disable_raw_mode()?;
+    }
+    result
+}
+
+pub fn execute_tail(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("tail", args, "f", "n")?;
+    let count = line_count(&parsed, "tail")?;
+    let operands = inputs(&parsed.operands);
+    let with_headers = operands.len() > 1;
+    let mut followed = Vec::new();
+    let mut current = None;
+
+    for (i, operand) in operands.iter().enumerate() {
+        let reader = match open_input(operand, env) {
+            Ok(reader) => reader,
+            Err(e) => {
+                report("tail", operand, &e);
+                continue;
+            }
+        };
+        if with_headers {
+            write_header(out, display_name(operand), i == 0)?;
+        }
+        for line in last_lines(reader, count)? {
+            writeln!(out, "{}", line)?;
+        }
+        current = None;
+
+        // Only files can be followed; stdin has nothing more to give once it ends
+        if parsed.has('f') && *operand != "-" {
+            let file = File::open(env.resolve_path(operand))?;
+            let position = file.metadata()?.len();
+            followed.push(FollowedFile { name: operand.to_string(), file, position });
+            current = Some(followed.len() - 1);
+        }
+    }
+    out.flush()?;
+
+    if followed.is_empty() {
+        return Ok(());
+    }
+    follow(&mut followed, with_headers, current, out)
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    bytes: usize,
+}
+
+fn count_reader(mut reader: Box<dyn BufRead>) -> io::Result<Counts> {
+    let mut counts = Counts::default();
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        let n = reader.read_until(b'\n', &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        counts.bytes += n;
+        if buf.ends_with(b"\n") {
+            counts.lines += 1;
+        }
+        counts.words += buf.split(|b| b.is_ascii_whitespace()).filter(|word| !word.is_empty()).count();
+    }
+    Ok(counts)
+}
+
+pub fn execute_wc(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("wc", args, "lwc", "")?;
+    // No flags means all three counts, like wc everywhere
+    let all = parsed.flags.is_empty();
+    let (show_lines, show_words, show_bytes) = (all || parsed.has('l'), all || parsed.has('w'), all || parsed.has('c'));
+
+    let mut results = Vec::new();
+    for operand in inputs(&parsed.operands) {
+        match open_input(operand, env).and_then(count_reader) {
+            Ok(counts) => results.push((operand, counts)),
+            Err(e) => report("wc", operand, &e),
+        }
+    }
+    if results.len() > 1 {
+        let total = results.iter().fold(Counts::default(), |total, (_, counts)| Counts {
+            lines: total.lines + counts.lines,
+            words: total.words + counts.words,
+            bytes: total.bytes + counts.bytes,
+        });
+        results.push(("total", total));
+    }
+
+    let width = results.iter().map(|(_, counts)| counts.bytes.max(counts.lines).to_string().len()).max().unwrap_or(1);
+    for (operand, counts) in results {
+        let mut row = String::new();
+        for (shown, value) in [(show_lines, counts.lines), (show_words, counts.words), (show_bytes, counts.bytes)] {
+            if shown {
+                row.push_str(&format!("{:>width$} ", value, width = width));
+            }
+        }
+        if operand != "-" {
+            row.push_str(operand);
+        }
+        writeln!(out, "{}", row.trim_end())?;
+    }
+    Ok(())
+}
+
+// Leading number of a line for `sort -n`; lines without one sort first, as in GNU sort
+fn numeric_key(line: &str) -> f64 {
+    let trimmed = line.trim_start();
+    let end = trimmed.char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(trimmed.len(), |(i, _)| i);
+    trimmed[..end].parse().unwrap_or(f64::NEG_INFINITY)
+}
+
+pub fn execute_sort(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("sort", args, "rnuf", "")?;
+    let mut lines = read_all_lines("sort", &parsed.operands, env)?;
+
+    let fold = parsed.has('f');
+    let key = |line: &String| if fold { line.to_lowercase() } else { line.clone() };
+    if parsed.has('n') {
+        lines.sort_by(|a, b| numeric_key(a).total_cmp(&numeric_key(b)).then_with(|| a.cmp(b)));
+    } else {
+        lines.sort_by_cached_key(key);
+    }
+    if parsed.has('u') {
+        lines.dedup_by(|a, b| if parsed.has('n') { numeric_key(a) == numeric_key(b) } else { key(a) == key(b) });
+    }
+    if parsed.has('r') {
+        lines.reverse();
+    }
+
+    for line in lines {
+        writeln!(out, "{}", line)?;
+    }
+    Ok(())
+}
+
+pub fn execute_uniq(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let parsed = parse_args("uniq", args, "cdui", "")?;
+    let ignore_case = parsed.has('i');
+    let same = |a: &str, b: &str| if ignore_case { a.to_lowercase() == b.to_lowercase() } else { a == b };
+
+    // Only adjacent duplicates are merged, which is why uniq usually follows sort
+    let mut groups: Vec<(String, usize)> = Vec::new();
+    for line in read_all_lines("uniq", &parsed.operands, env)? {
+        match groups.last_mut() {
+            Some((first, count)) if same(first, &line) => *count += 1,
+            _ => groups.push((line, 1)),
+        }
+    }
+
+    for (line, count) in groups {
+        if (parsed.has('d') && count < 2) || (parsed.has('u') && count > 1) {
+            continue;
+        }
+        if parsed.has('c') {
+            writeln!(out, "{:>7} {}", count, line)?;
+        } else {
+            writeln!(out, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Command = fn(&[&str], &Environment, &mut Vec<u8>) -> io::Result<()>;
+
+    // Runs a command on `input`, given as a file, and returns what it wrote
+    fn run(command: Command, args: &[&str], input: &str) -> String {
+        let file = std::env::temp_dir().join(format!("partermai-text-{}-{:?}", std::process::id(), std::thread::current().id()));
+        std::fs::write(&file, input).unwrap();
+        let path = file.to_string_lossy().to_string();
+        let mut args = args.to_vec();
+        args.push(&path);
+        let mut out = Vec::new();
+        command(&args, &Environment::new(), &mut out).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn counts(input: &'static str) -> (usize, usize, usize) {
+        let counts = count_reader(Box::new(input.as_bytes())).unwrap();
+        (counts.lines, counts.words, counts.bytes)
+    }
+
+    #[test]
+    fn numeric_key_reads_the_leading_number() {
+        assert_eq!(numeric_key("42 apples"), 42.0);
+        assert_eq!(numeric_key("  -3.5kg"), -3.5);
+        assert_eq!(numeric_key("+7"), 7.0);
+        assert_eq!(numeric_key("apples"), f64::NEG_INFINITY);
+        assert_eq!(numeric_key("1-2"), 1.0);
+    }
+
+    #[test]
+    fn count_reader_counts_like_wc() {
+        assert_eq!(counts(""), (0, 0, 0));
+        assert_eq!(counts("one two\n  three\n"), (2, 3, 16));
+        // A last line without a newline has words but isn't counted as a line
+        assert_eq!(counts("a\nb c"), (1, 3, 5));
+        assert_eq!(counts("é\n"), (1, 1, 3));
+    }
+
+    #[test]
+    fn sort_orders_lines() {
+        assert_eq!(run(execute_sort, &[], "b\nB\na\n"), "B\na\nb\n");
+        assert_eq!(run(execute_sort, &["-f"], "b\nC\na\n"), "a\nb\nC\n");
+        assert_eq!(run(execute_sort, &["-r"], "a\nc\nb\n"), "c\nb\na\n");
+    }
+
+    #[test]
+    fn sort_n_compares_numbers_and_u_drops_repeats() {
+        assert_eq!(run(execute_sort, &["-n"], "10\n9\nx\n-1\n"), "x\n-1\n9\n10\n");
+        assert_eq!(run(execute_sort, &["-u"], "b\na\nb\n"), "a\nb\n");
+        assert_eq!(run(execute_sort, &["-nu"], "2\n02\n1\n"), "1\n02\n");
+    }
+
+    #[test]
+    fn uniq_merges_adjacent_lines_only() {
+        assert_eq!(run(execute_uniq, &[], "a\na\nb\na\n"), "a\nb\na\n");
+        assert_eq!(run(execute_uniq, &["-i"], "a\nA\nb\n"), "a\nb\n");
+    }
+
+    #[test]
+    fn uniq_counts_and_filters_groups() {
+        assert_eq!(run(execute_uniq, &["-c"], "a\na\nb\n"), "      2 a\n      1 b\n");
+        assert_eq!(run(execute_uniq, &["-d"], "a\na\nb\n"), "a\n");
+        assert_eq!(run(execute_uniq, &["-u"], "a\na\nb\n"), "b\n");
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{self, IsTerminal, Write};
+#[cfg(unix)]
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use colored::*;
+use crate::config::TimingConfig;
+
+// Set when the user types into a running program, which means they weren't away
+static INPUT_SEEN: AtomicBool = AtomicBool::new(false);
+// Set when the command line started an external program. Builtins that take over the
+// screen wait on the user, not the other way round, so only programs are timed.
+static PROGRAM_RAN: AtomicBool = AtomicBool::new(false);
+// Peak memory of the last program reaped, in bytes
+static CHILD_MAX_RSS: Mutex<Option<u64>> = Mutex::new(None);
+
+pub fn note_input() {
+    INPUT_SEEN.store(true, Ordering::Relaxed);
+}
+
+pub fn note_program() {
+    PROGRAM_RAN.store(true, Ordering::Relaxed);
+}
+
+#[cfg(unix)]
+fn rusage(who: libc::c_int) -> libc::rusage {
+    // SAFETY: getrusage fills in the zeroed struct
+    unsafe {
+        let mut usage = std::mem::zeroed::<libc::rusage>();
+        libc::getrusage(who, &mut usage);
+        usage
+    }
+}
+
+#[cfg(unix)]
+fn to_duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+// ru_maxrss is in kilobytes on Linux but in bytes on macOS
+#[cfg(unix)]
+fn max_rss_bytes(usage: &libc::rusage) -> u64 {
+    let max_rss = usage.ru_maxrss as u64;
+    if cfg!(target_os = "macos") { max_rss } else { max_rss * 1024 }
+}
+
+// Like Child::try_wait, but through wait4 so the program's peak memory is known
+#[cfg(unix)]
+fn wait4(child: &Child, flags: libc::c_int) -> io::Result<Option<ExitStatus>> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    // SAFETY: wait4 fills in the zeroed struct
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        // SAFETY: status and usage are valid for writes
+        match unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, flags, &mut usage) } {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.kind() != io::ErrorKind::Interrupted {
+                    return Err(e);
+                }
+            }
+            0 => return Ok(None),
+            _ => {
+                if let Ok(mut max_rss) = CHILD_MAX_RSS.lock() {
+                    *max_rss = Some(max_rss_bytes(&usage));
+                }
+                return Ok(Some(ExitStatus::from_raw(status)));
+            }
+        }
+    }
+}
+
+// These replace Child::wait and Child::try_wait; don't mix them with the Child's own
+#[cfg(unix)]
+pub fn wait_child(child: &Child) -> io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = wait4(child, 0)? {
+            return Ok(status);
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn try_wait_child(child: &Child) -> io::Result<Option<ExitStatus>> {
+    wait4(child, libc::WNOHANG)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 60.0 {
+        format!("{:.1}s", secs)
+    } else if secs < 3600.0 {
+        format!("{}m {}s", duration.as_secs() / 60, duration.as_secs() % 60)
+    } else {
+        format!("{}h {}m", duration.as_secs() / 3600, duration.as_secs() % 3600 / 60)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Measures one command line: wall time, plus CPU time spent in Partermai and its children
+pub struct Timer {
+    started: Instant,
+    #[cfg(unix)]
+    own: libc::rusage,
+    #[cfg(unix)]
+    children: libc::rusage,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        INPUT_SEEN.store(false, Ordering::Relaxed);
+        PROGRAM_RAN.store(false, Ordering::Relaxed);
+        if let Ok(mut max_rss) = CHILD_MAX_RSS.lock() {
+            *max_rss = None;
+        }
+        Self {
+            started: Instant::now(),
+            #[cfg(unix)]
+            own: rusage(libc::RUSAGE_SELF),
+            #[cfg(unix)]
+            children: rusage(libc::RUSAGE_CHILDREN),
+        }
+    }
+
+    // The `time` report: real, user and sys time, and the peak memory of the program
+    // that ran (or of Partermai itself for builtins)
+    pub fn report(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}  {:.3}s", "real  ".dimmed(), self.started.elapsed().as_secs_f64())?;
+        #[cfg(unix)]
+        {
+            let (own, children) = (rusage(libc::RUSAGE_SELF), rusage(libc::RUSAGE_CHILDREN));
+            let spent = |now: &libc::rusage, then: &libc::rusage, user: bool| {
+                let (now, then) = if user { (now.ru_utime, then.ru_utime) } else { (now.ru_stime, then.ru_stime) };
+                to_duration(now).saturating_sub(to_duration(then))
+            };
+            let user = spent(&own, &self.own, true) + spent(&children, &self.children, true);
+            let sys = spent(&own, &self.own, false) + spent(&children, &self.children, false);
+            let max_rss = CHILD_MAX_RSS.lock().ok().and_then(|max_rss| *max_rss).unwrap_or_else(|| max_rss_bytes(&own));
+            writeln!(out, "{}  {:.3}s", "user  ".dimmed(), user.as_secs_f64())?;
+            writeln!(out, "{}  {:.3}s", "sys   ".dimmed(), sys.as_secs_f64())?;
+            writeln!(out, "{}  {}", "maxrss".dimmed(), format_bytes(max_rss))?;
+        }
+        Ok(())
+    }
+
+    // After every command that ran a program: note how long a slow one took, and if it was
+    // slow enough that the user probably looked away, ring the bell and post a desktop
+    // notification (OSC 9)
+    pub fn finish(&self, command: &str, config: &TimingConfig) {
+        if !PROGRAM_RAN.load(Ordering::Relaxed) {
+            return;
+        }
+        let elapsed = self.started.elapsed();
+        let secs = elapsed.as_secs_f64();
+        if config.threshold_secs > 0.0 && secs >= config.threshold_secs {
+            println!("{}", format!("took {}", format_duration(elapsed)).dimmed());
+        }
+        let away = !INPUT_SEEN.load(Ordering::Relaxed);
+        if config.notify_secs > 0.0 && secs >= config.notify_secs && away && io::stdout().is_terminal() {
+            print!("\x07\x1B]9;Partermai: '{}' finished after {}\x07", command, format_duration(elapsed));
+            let _ = io::stdout().flush();
+        }
+    }
+}
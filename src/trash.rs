@@ -0,0 +1,354 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use chrono::Local;
+use colored::*;
+use crate::fileops::{move_path, remove_path};
+use crate::Environment;
+
+// An entry in the trash, as described by its .trashinfo file
+pub struct TrashedItem {
+    pub name: String,
+    pub original: PathBuf,
+    pub deleted: String,
+    trash: TrashDir,
+}
+
+// The home trash, or the trash at the top of another volume. Moving a file into the home
+// trash from a different filesystem would mean copying it, so such files go to a trash on
+// their own volume instead, as the freedesktop.org spec asks.
+#[derive(Clone)]
+struct TrashDir {
+    path: PathBuf,
+    topdir: Option<PathBuf>, // A volume trash stores paths relative to the volume's top
+}
+
+impl TrashDir {
+    fn files(&self) -> PathBuf {
+        self.path.join("files")
+    }
+
+    fn info_path(&self, name: &str) -> PathBuf {
+        self.path.join("info").join(format!("{}.trashinfo", name))
+    }
+
+    // What goes in the Path= key for `path`
+    fn stored_path(&self, path: &Path) -> PathBuf {
+        match &self.topdir {
+            Some(topdir) => path.strip_prefix(topdir).unwrap_or(path).to_path_buf(),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn original_path(&self, stored: PathBuf) -> PathBuf {
+        match &self.topdir {
+            Some(topdir) if stored.is_relative() => topdir.join(stored),
+            _ => stored,
+        }
+    }
+}
+
+// The user's trash per the freedesktop.org spec: $XDG_DATA_HOME/Trash
+fn home_trash() -> io::Result<TrashDir> {
+    dirs::data_dir()
+        .map(|dir| TrashDir { path: dir.join("Trash"), topdir: None })
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No data directory for the trash"))
+}
+
+#[cfg(unix)]
+fn volume_trash(topdir: &Path) -> TrashDir {
+    // SAFETY: getuid has no preconditions
+    let uid = unsafe { libc::getuid() };
+    TrashDir { path: topdir.join(format!(".Trash-{}", uid)), topdir: Some(topdir.to_path_buf()) }
+}
+
+#[cfg(unix)]
+fn device_of(path: &Path) -> io::Result<u64> {
+    use std::os::unix::fs::MetadataExt;
+    // The trash may not exist yet, so go by the nearest directory that does
+    let existing = path.ancestors().find(|dir| dir.exists()).unwrap_or(path);
+    Ok(fs::metadata(existing)?.dev())
+}
+
+// The trash `path` should go to: the home trash if it is on the same filesystem, otherwise
+// the trash at the top of the volume `path` is on
+#[cfg(unix)]
+fn trash_for(path: &Path) -> io::Result<TrashDir> {
+    let home = home_trash()?;
+    let parent = path.parent().unwrap_or(path);
+    let device = device_of(parent)?;
+    if device == device_of(&home.path)? {
+        return Ok(home);
+    }
+    // The top of the volume is the highest directory still on the same device
+    let mut topdir = parent;
+    while let Some(up) = topdir.parent() {
+        if device_of(up)? != device {
+            break;
+        }
+        topdir = up;
+    }
+    Ok(volume_trash(topdir))
+}
+
+#[cfg(not(unix))]
+fn trash_for(_path: &Path) -> io::Result<TrashDir> {
+    home_trash()
+}
+
+// Mount points, to look for volume trashes; only Linux lists them where we can read them
+#[cfg(target_os = "linux")]
+fn mount_points() -> Vec<PathBuf> {
+    let Ok(mounts) = fs::read_to_string("/proc/self/mounts") else { return Vec::new() };
+    let mut points: Vec<PathBuf> = mounts.lines()
+        .filter_map(|line| line.split(' ').nth(1))
+        // Spaces and other awkward bytes are written as octal escapes, like \040
+        .map(|field| PathBuf::from(field.replace("\\040", " ").replace("\\011", "\t").replace("\\012", "\n").replace("\\134", "\\")))
+        .collect();
+    // The same directory can be mounted over more than once
+    points.sort();
+    points.dedup();
+    points
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_points() -> Vec<PathBuf> {
+    Vec::new()
+}
+
+// The home trash and every volume trash that exists
+fn all_trashes() -> io::Result<Vec<TrashDir>> {
+    let mut trashes = vec![home_trash()?];
+    #[cfg(unix)]
+    trashes.extend(mount_points().iter().map(|topdir| volume_trash(topdir)).filter(|trash| trash.path.is_dir()));
+    Ok(trashes)
+}
+
+// Percent-encodes a path for the Path= key, leaving '/' and unreserved characters alone
+fn encode_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn decode_path(encoded: &str) -> PathBuf {
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+// A name not yet used in the trash: "file", then "file.2", "file.3", ...
+fn unique_name(trash: &TrashDir, base: &str) -> String {
+    let files = trash.files();
+    let mut name = base.to_string();
+    let mut n = 1;
+    while files.join(&name).symlink_metadata().is_ok() || trash.info_path(&name).exists() {
+        n += 1;
+        name = format!("{}.{}", base, n);
+    }
+    name
+}
+
+#[cfg(unix)]
+fn create_trash(trash: &TrashDir) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    // Other users can write to the top of a shared volume, so only we may look inside
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(&trash.path)?;
+    fs::create_dir_all(trash.files())?;
+    fs::create_dir_all(trash.path.join("info"))
+}
+
+#[cfg(not(unix))]
+fn create_trash(trash: &TrashDir) -> io::Result<()> {
+    fs::create_dir_all(trash.files())?;
+    fs::create_dir_all(trash.path.join("info"))
+}
+
+// Moves `path` (which must be absolute) into the trash and returns its name there
+pub fn move_to_trash(path: &Path) -> io::Result<String> {
+    let trash = trash_for(path)?;
+    if let Err(e) = create_trash(&trash) {
+        return Err(match &trash.topdir {
+            // Copying into the home trash instead would be slow and surprising
+            Some(topdir) => io::Error::new(e.kind(), format!(
+                "cannot create a trash on the volume at {} ({}); use --permanent to delete", topdir.display(), e)),
+            None => e,
+        });
+    }
+
+    let base = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "cannot trash this path"))?;
+    let name = unique_name(&trash, &base);
+
+    // The info file is written first, as the spec asks, so a crash never leaves an orphan
+    let info = format!("[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(&trash.stored_path(path)), Local::now().format("%Y-%m-%dT%H:%M:%S"));
+    fs::write(trash.info_path(&name), info)?;
+
+    if let Err(e) = move_path(path, &trash.files().join(&name)) {
+        let _ = fs::remove_file(trash.info_path(&name));
+        return Err(e);
+    }
+    Ok(name)
+}
+
+fn read_info(trash: &TrashDir, name: &str) -> io::Result<TrashedItem> {
+    let content = fs::read_to_string(trash.info_path(name))?;
+    let mut original = None;
+    let mut deleted = String::new();
+    for line in content.lines() {
+        if let Some(value) = line.strip_prefix("Path=") {
+            original = Some(trash.original_path(decode_path(value)));
+        } else if let Some(value) = line.strip_prefix("DeletionDate=") {
+            deleted = value.replace('T', " ");
+        }
+    }
+    let original = original
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("{}: missing Path in trash info", name)))?;
+    Ok(TrashedItem { name: name.to_string(), original, deleted, trash: trash.clone() })
+}
+
+// Trashed items from every trash, oldest first
+pub fn list() -> io::Result<Vec<TrashedItem>> {
+    let mut items = Vec::new();
+    for trash in all_trashes()? {
+        let Ok(entries) = fs::read_dir(trash.path.join("info")) else { continue };
+        items.extend(entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                let name = file_name.strip_suffix(".trashinfo")?;
+                read_info(&trash, name).ok()
+            }));
+    }
+    items.sort_by(|a, b| a.deleted.cmp(&b.deleted));
+    Ok(items)
+}
+
+fn restore_item(item: TrashedItem) -> io::Result<PathBuf> {
+    if item.original.symlink_metadata().is_ok() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+            format!("'{}' already exists", item.original.display())));
+    }
+    if let Some(parent) = item.original.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_path(&item.trash.files().join(&item.name), &item.original)?;
+    fs::remove_file(item.trash.info_path(&item.name))?;
+    Ok(item.original)
+}
+
+// Moves a trashed item back to where it came from; the most recent one if several trashes
+// have an item of that name
+pub fn restore(name: &str) -> io::Result<PathBuf> {
+    let item = list()?.into_iter()
+        .rev()
+        .find(|item| item.name == name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("'{}' is not in the trash", name)))?;
+    restore_item(item)
+}
+
+// Permanently deletes everything in the trash, returning how many items were removed
+pub fn empty() -> io::Result<usize> {
+    let items = list()?;
+    for item in &items {
+        let file = item.trash.files().join(&item.name);
+        if file.symlink_metadata().is_ok() {
+            remove_path(&file, true)?;
+        }
+        fs::remove_file(item.trash.info_path(&item.name))?;
+    }
+    Ok(items.len())
+}
+
+pub fn execute_trash(args: &[&str], env: &Environment) -> io::Result<()> {
+    match args.first().copied() {
+        Some("list") | None => {
+            let items = list()?;
+            if items.is_empty() {
+                println!("{}", "The trash is empty.".green());
+            }
+            for item in items {
+                println!("  {}  {}  {}", item.deleted.dimmed(), item.name.bright_cyan(), env.tilde_path(&item.original));
+            }
+            Ok(())
+        }
+        Some("restore") => {
+            let Some(target) = args.get(1) else {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: trash restore <name|original path>"));
+            };
+            // Accept the name shown by `trash list` or the original location (most recent wins)
+            let resolved = env.resolve_path(target);
+            let item = list()?.into_iter()
+                .rev()
+                .find(|item| item.name == *target || item.original == resolved)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("'{}' is not in the trash", target)))?;
+            let restored = restore_item(item)?;
+            println!("{} {}", "Restored".green(), env.tilde_path(&restored));
+            Ok(())
+        }
+        Some("empty") => {
+            let count = empty()?;
+            println!("{} {} item(s) permanently deleted", "Trash emptied:".green(), count);
+            Ok(())
+        }
+        Some(other) => Err(io::Error::new(io::ErrorKind::InvalidInput,
+            format!("Unknown trash command '{}'. Use list, restore or empty", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_path_escapes_reserved_bytes() {
+        assert_eq!(encode_path(Path::new("/home/me/a-b_c.txt")), "/home/me/a-b_c.txt");
+        assert_eq!(encode_path(Path::new("/tmp/my file%.txt")), "/tmp/my%20file%25.txt");
+        assert_eq!(encode_path(Path::new("/tmp/é")), "/tmp/%C3%A9");
+    }
+
+    #[test]
+    fn decode_path_reverses_encode_path() {
+        for path in ["/tmp/my file%.txt", "/tmp/é/ü", "/plain"] {
+            assert_eq!(decode_path(&encode_path(Path::new(path))), PathBuf::from(path));
+        }
+    }
+
+    #[test]
+    fn decode_path_keeps_malformed_escapes() {
+        assert_eq!(decode_path("/tmp/100%"), PathBuf::from("/tmp/100%"));
+        assert_eq!(decode_path("/tmp/%zz"), PathBuf::from("/tmp/%zz"));
+    }
+
+    #[test]
+    fn volume_trash_stores_paths_relative_to_its_top() {
+        let trash = TrashDir { path: PathBuf::from("/mnt/usb/.Trash-1000"), topdir: Some(PathBuf::from("/mnt/usb")) };
+        let stored = trash.stored_path(Path::new("/mnt/usb/photos/a.jpg"));
+        assert_eq!(stored, PathBuf::from("photos/a.jpg"));
+        assert_eq!(trash.original_path(stored), PathBuf::from("/mnt/usb/photos/a.jpg"));
+        // Absolute paths written by other tools are taken as they are
+        assert_eq!(trash.original_path(PathBuf::from("/elsewhere/b")), PathBuf::from("/elsewhere/b"));
+    }
+}
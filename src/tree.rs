@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use ignore::WalkBuilder;
+use crate::config::LsConfig;
+use crate::ls::{icon_for, FileKind, LsColors};
+use crate::Environment;
+
+#[derive(Default)]
+pub struct TreeOptions {
+    pub show_hidden: bool,        // -a
+    pub dirs_only: bool,          // -d
+    pub max_depth: Option<usize>, // -L <depth>
+}
+
+struct TreeEntry {
+    name: String,
+    path: PathBuf,
+    kind: FileKind,
+}
+
+// Running totals for the summary line
+#[derive(Default)]
+struct Counts {
+    dirs: usize,
+    files: usize,
+}
+
+pub fn execute_tree(path: Option<&str>, env: &Environment, options: &TreeOptions, config: &LsConfig) -> io::Result<()> {
+    let target_path = path.map_or_else(|| env.current_dir.clone(), |p| env.resolve_path(p));
+    if !target_path.is_dir() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "Directory not found"));
+    }
+
+    // Walk once, honoring .gitignore, and group entries by their parent directory
+    let dirs_only = options.dirs_only;
+    let walker = WalkBuilder::new(&target_path)
+        .hidden(!options.show_hidden)
+        .max_depth(options.max_depth)
+        .filter_entry(move |entry| !dirs_only || entry.file_type().is_some_and(|t| t.is_dir()))
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+
+    let mut children: HashMap<PathBuf, Vec<TreeEntry>> = HashMap::new();
+    for entry in walker.filter_map(Result::ok) {
+        if entry.depth() == 0 {
+            continue;
+        }
+        let Some(parent) = entry.path().parent() else { continue };
+        children.entry(parent.to_path_buf()).or_default().push(TreeEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path: entry.path().to_path_buf(),
+            kind: FileKind::of(entry.path()),
+        });
+    }
+
+    let colors = LsColors::from_env();
+    let root_name = path.unwrap_or(".");
+    println!("{}", colors.paint(root_name, FileKind::Directory));
+
+    let mut counts = Counts::default();
+    print_children(&target_path, "", &children, &colors, config, &mut counts);
+
+    println!();
+    if options.dirs_only {
+        println!("{} {}", counts.dirs, plural(counts.dirs, "directory", "directories"));
+    } else {
+        println!("{} {}, {} {}",
+            counts.dirs, plural(counts.dirs, "directory", "directories"),
+            counts.files, plural(counts.files, "file", "files"));
+    }
+    Ok(())
+}
+
+fn print_children(
+    dir: &Path,
+    prefix: &str,
+    children: &HashMap<PathBuf, Vec<TreeEntry>>,
+    colors: &LsColors,
+    config: &LsConfig,
+    counts: &mut Counts,
+) {
+    let Some(entries) = children.get(dir) else { return };
+
+    for (i, entry) in entries.iter().enumerate() {
+        let last = i + 1 == entries.len();
+        let branch = if last { "└── " } else { "├── " };
+
+        let mut label = colors.paint(&entry.name, entry.kind);
+        if config.icons {
+            label = format!("{} {}", icon_for(&entry.name, entry.kind), label);
+        }
+        println!("{}{}{}", prefix, branch, label);
+
+        if entry.kind == FileKind::Directory {
+            counts.dirs += 1;
+            let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+            print_children(&entry.path, &child_prefix, children, colors, config, counts);
+        } else {
+            counts.files += 1;
+        }
+    }
+}
+
+fn plural<'a>(count: usize, one: &'a str, many: &'a str) -> &'a str {
+    if count == 1 { one } else { many }
+}
@@ -0,0 +1,94 @@
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use colored::*;
+use crate::completion::BUILTINS;
+use crate::Environment;
+
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else { return false };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+// Every executable called `name` on $PATH, in search order; names with a slash are
+// taken as paths relative to the session directory instead
+pub fn find_executables(name: &str, env: &Environment) -> Vec<PathBuf> {
+    if name.contains('/') {
+        let path = env.resolve_path(name);
+        return if is_executable(&path) { vec![path] } else { Vec::new() };
+    }
+    let Some(paths) = env::var_os("PATH") else { return Vec::new() };
+    env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .filter(|path| is_executable(path))
+        .collect()
+}
+
+fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name)
+}
+
+fn not_found(cmd: &str, name: &str) {
+    println!("{}: {}: {}: not found", "Error".red(), cmd, name);
+    crate::history::mark_failed();
+}
+
+// `type ls` says which one Partermai will run: aliases win over builtins, which win over $PATH
+pub fn execute_type(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    if args.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: type <name>..."));
+    }
+    for name in args {
+        if let Some(expansion) = env.aliases.get(*name) {
+            writeln!(out, "{} is an alias for {}", name.bright_cyan(), expansion)?;
+        } else if is_builtin(name) {
+            writeln!(out, "{} is a Partermai builtin", name.bright_cyan())?;
+            if let Some(path) = find_executables(name, env).first() {
+                writeln!(out, "  {} is also {} (use 'command {}' to run it)", name, path.display(), name)?;
+            }
+        } else if let Some(path) = find_executables(name, env).first() {
+            writeln!(out, "{} is {}", name.bright_cyan(), path.display())?;
+        } else {
+            not_found("type", name);
+        }
+    }
+    Ok(())
+}
+
+pub fn execute_which(args: &[&str], env: &Environment, out: &mut impl Write) -> io::Result<()> {
+    let all = args.contains(&"-a");
+    let names: Vec<&str> = args.iter().copied().filter(|arg| *arg != "-a").collect();
+    if names.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Usage: which [-a] <name>..."));
+    }
+    for name in names {
+        if let Some(expansion) = env.aliases.get(name) {
+            writeln!(out, "{}: aliased to {}", name, expansion)?;
+            if !all {
+                continue;
+            }
+        }
+        let builtin = is_builtin(name);
+        if builtin {
+            writeln!(out, "{}: Partermai builtin", name)?;
+        }
+        // -a lists the shadowed executables too
+        let executables = find_executables(name, env);
+        let shown = if all { executables.len() } else if builtin { 0 } else { 1 };
+        for path in executables.iter().take(shown) {
+            writeln!(out, "{}", path.display())?;
+        }
+        if !builtin && executables.is_empty() {
+            not_found("which", name);
+        }
+    }
+    Ok(())
+}